@@ -1,46 +1,143 @@
-/// Stochaistic optimisation based on simulated annealing.
-/// Math is shamelessly taken from: http://mkweb.bcgsc.ca/carpalx/?simulated_annealing
-/// This code is written to be generic and can be reused for other applications.
+//! Simulated annealing of keyboard layouts.
+//! Math is shamelessly taken from: http://mkweb.bcgsc.ca/carpalx/?simulated_annealing
 
 extern crate rand;
 
 use std::f64;
 use std::ops::Range;
-use self::rand::thread_rng;
 use self::rand::Rng;
+use self::rand::StdRng;
+
+use best::BestLayouts;
+use layout::Layout;
+use optimizer;
+use optimizer::Optimizer;
+use optimizer::Problem;
+use optimizer::Progress;
 
 // These values are taken from Carpalx, with T0 adjusted for the scale that our
 // penalty model outputs.
-const T0: f64   = 1.5;
-const K:  f64   = 10.0;
-const P0: f64   = 1.0;
-const N:  usize = 15000;
+pub const T0: f64   = 1.5;
+pub const K:  f64   = 10.0;
+pub const P0: f64   = 1.0;
+pub const N:  usize = 15000;
 const KN: f64   = K / (N as f64);
 
+// Each step shuffles the accepted layout by up to `num_swaps` swaps, and
+// accepts the result if it is better or, with a chance that falls as the
+// temperature does, if it is worse.
+pub struct Annealing<'a>
+{
+	problem:   Problem<'a>,
+	num_swaps: usize,
+	rng:       StdRng,
+	best:      BestLayouts,
+	accepted:  Option<(Layout, f64)>,
+	iteration: usize,
+}
+
+impl <'a> Annealing<'a>
+{
+	pub fn new(problem: Problem<'a>, top_layouts: usize, min_distance: usize, num_swaps: usize, seed: u64)
+	-> Annealing<'a>
+	{
+		Annealing {
+			problem,
+			num_swaps,
+			rng: optimizer::seeded_rng(seed),
+			best: BestLayouts::with_min_distance(top_layouts, min_distance),
+			accepted: None,
+			iteration: 0,
+		}
+	}
+}
+
+impl <'a> Optimizer for Annealing<'a>
+{
+	fn name(&self)
+	-> &'static str
+	{
+		"annealing"
+	}
+
+	fn init(&mut self, layout: &Layout, penalty: f64)
+	{
+		self.accepted = Some((layout.clone(), penalty));
+		self.iteration = 0;
+	}
+
+	fn step(&mut self)
+	-> bool
+	{
+		let i = self.iteration + 1;
+		if !get_simulation_range().contains(&i) {
+			return false;
+		}
+		self.iteration = i;
+
+		let (accepted_layout, accepted_penalty) = self.accepted.clone().expect("annealing before init");
+
+		// Copy and shuffle this iteration of the layout.
+		let mut curr_layout = accepted_layout;
+		let times = self.rng.gen::<usize>() % self.num_swaps + 1;
+		curr_layout.shuffle(times, self.problem.mask, &mut self.rng);
+		let penalty = self.problem.penalty(&curr_layout);
+
+		// Probabilistically accept worse transitions; always accept better
+		// transitions.
+		if accept_transition(penalty - accepted_penalty, i, &mut self.rng) {
+			self.accepted = Some((curr_layout.clone(), penalty));
+			self.best.insert(curr_layout, penalty);
+		}
+		true
+	}
+
+	fn best(&self)
+	-> &BestLayouts
+	{
+		&self.best
+	}
+
+	fn progress(&self)
+	-> Progress
+	{
+		Progress {
+			step:    self.iteration,
+			penalty: self.accepted.as_ref().map_or(f64::NAN, |a| a.1),
+		}
+	}
+}
+
 // T(i) = T0 exp(-ik/N)
-fn temperature(i: usize)
+pub fn temperature(i: usize)
 -> f64
 {
 	T0 * f64::exp(-(i as f64) * KN)
 }
 
-// p(dE, i) = p0 exp(-dE/T(i))
-fn cutoff_p(de: f64, i: usize)
+// p(dE, T) = p0 exp(-dE/T)
+fn cutoff_p(de: f64, t: f64)
 -> f64
 {
-	let t = temperature(i);
 	P0 * f64::exp(-de / t)
 }
 
 // For positive dE, accept if r < p_dE where r ~ Uniform(0, 1)
-pub fn accept_transition(de: f64, i: usize)
+pub fn accept_transition<R: Rng>(de: f64, i: usize, rng: &mut R)
+-> bool
+{
+	accept_at(de, temperature(i), rng)
+}
+
+// The same at a fixed temperature T.
+pub fn accept_at<R: Rng>(de: f64, t: f64, rng: &mut R)
 -> bool
 {
 	if de < 0.0 {
 		true
 	} else {
-		let p_de = cutoff_p(de, i);
-		let r = thread_rng().next_f64();
+		let p_de = cutoff_p(de, t);
+		let r = rng.next_f64();
 		r < p_de
 	}
 }
@@ -0,0 +1,123 @@
+//! Keeping the best layouts that an optimiser finds.
+
+use std::cmp::Ordering;
+
+use layout::Layout;
+
+// The layouts with the lowest penalties seen so far, at most `capacity` of
+// them, best first. Each layout is kept only once, however often it is found;
+// see `Layout`'s equality.
+pub struct BestLayouts
+{
+	capacity:     usize,
+	min_distance: usize,
+	entries:      Vec<(Layout, f64)>,
+}
+
+impl BestLayouts
+{
+	pub fn new(capacity: usize)
+	-> BestLayouts
+	{
+		BestLayouts::with_min_distance(capacity, 1)
+	}
+
+	// Keeps only layouts that are at least `min_distance` swaps apart, so that
+	// the best layouts aren't all small variations of one. Of layouts that are
+	// closer, the better one is kept.
+	pub fn with_min_distance(capacity: usize, min_distance: usize)
+	-> BestLayouts
+	{
+		BestLayouts {
+			capacity,
+			min_distance,
+			entries: Vec::with_capacity(capacity + 1),
+		}
+	}
+
+	// Whether a layout with this penalty might be kept, which is cheaper to
+	// find out than whether it is.
+	pub fn admits(&self, penalty: f64)
+	-> bool
+	{
+		if self.entries.len() < self.capacity {
+			return true;
+		}
+		match self.entries.last() {
+			Some(worst) => compare(penalty, worst.1) == Ordering::Less,
+			None => false,
+		}
+	}
+
+	// Offers a layout with its penalty, and returns whether it was kept. Of
+	// layouts with equal penalties, the one found first ranks first.
+	pub fn insert(&mut self, layout: Layout, penalty: f64)
+	-> bool
+	{
+		if !self.admits(penalty) {
+			return false;
+		}
+		let min_distance = self.min_distance;
+		if self.entries.iter().any(|e| compare(e.1, penalty) != Ordering::Greater && near(&e.0, &layout, min_distance)) {
+			return false;
+		}
+		self.entries.retain(|e| !near(&e.0, &layout, min_distance));
+
+		let i = self.entries.iter()
+			.position(|e| compare(penalty, e.1) == Ordering::Less)
+			.unwrap_or(self.entries.len());
+		self.entries.insert(i, (layout, penalty));
+		self.entries.truncate(self.capacity);
+		true
+	}
+
+	pub fn len(&self)
+	-> usize
+	{
+		self.entries.len()
+	}
+
+	pub fn is_empty(&self)
+	-> bool
+	{
+		self.entries.is_empty()
+	}
+
+	// The best layout and its penalty.
+	pub fn best(&self)
+	-> Option<&(Layout, f64)>
+	{
+		self.entries.first()
+	}
+
+	pub fn layouts(&self)
+	-> impl Iterator<Item = &Layout>
+	{
+		self.entries.iter().map(|e| &e.0)
+	}
+
+	// The layouts and their penalties, best first.
+	pub fn into_vec(self)
+	-> Vec<(Layout, f64)>
+	{
+		self.entries
+	}
+}
+
+// Penalties that can't be compared, which are NaN, count as equal.
+fn compare(a: f64, b: f64)
+-> Ordering
+{
+	a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+}
+
+// Whether two layouts are fewer than `min_distance` swaps apart.
+fn near(a: &Layout, b: &Layout, min_distance: usize)
+-> bool
+{
+	match min_distance {
+		0 => false,
+		1 => a == b,
+		_ => a.swap_distance(b).is_some_and(|d| d < min_distance),
+	}
+}
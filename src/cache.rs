@@ -0,0 +1,242 @@
+//! On-disk cache of processed quartad lists, keyed by a hash of the corpus
+//! files' paths, sizes and modification times and the settings used to
+//! process them.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+use corpus;
+use corpus::CorpusFormat;
+use error::Error;
+use error::Result;
+use layout;
+use layout::LayoutPosMap;
+use penalty::QuartadList;
+use registry::NamedLayout;
+
+// Bump this whenever the file format or the way quartads are counted changes,
+// so that stale caches are ignored.
+const CACHE_VERSION: u32 = 3;
+const CACHE_MAGIC: &[u8; 4] = b"KGQC";
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME:  u64 = 0x100000001b3;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum CacheMode
+{
+	// Load from the cache if possible, otherwise build and store.
+	Use,
+	// Ignore any cached table, but store the freshly built one.
+	Rebuild,
+	// Neither read nor write the cache.
+	Disabled,
+}
+
+// FNV-1a. We need a hash that is stable across runs and compiler versions,
+// which rules out `DefaultHasher`.
+struct Fnv(u64);
+
+impl Fnv
+{
+	fn write(&mut self, bytes: &[u8])
+	{
+		for b in bytes {
+			self.0 ^= *b as u64;
+			self.0 = self.0.wrapping_mul(FNV_PRIME);
+		}
+	}
+
+	fn write_u64(&mut self, n: u64)
+	{
+		self.write(&n.to_le_bytes());
+	}
+}
+
+// Default cache directory: $XDG_CACHE_HOME/keygen or ~/.cache/keygen.
+pub fn default_dir()
+-> PathBuf
+{
+	if let Some(dir) = env::var_os("XDG_CACHE_HOME") {
+		if !dir.is_empty() {
+			return PathBuf::from(dir).join("keygen");
+		}
+	}
+	match env::var_os("HOME") {
+		Some(home) => PathBuf::from(home).join(".cache").join("keygen"),
+		None => env::temp_dir().join("keygen"),
+	}
+}
+
+// Reads the quartads of the corpus named by `spec`, going through the cache
+// in `dir` according to `mode`. Failing to write the cache is not an error.
+// Keystroke logs may contain keys such as Backspace that only `layouts` have,
+// so those are kept too.
+pub fn read_quartads(
+	spec:    &str,
+	format:  &CorpusFormat,
+	layouts: &[NamedLayout],
+	dir:     &Path,
+	mode:     CacheMode)
+-> Result<(QuartadList, usize)>
+{
+	let mut position_map = layout::INIT_LAYOUT.get_position_map();
+	if let CorpusFormat::KeyLog = *format {
+		for l in layouts.iter() {
+			position_map.extend(&l.layout.get_position_map());
+		}
+	}
+
+	let paths = corpus::expand_paths(spec)?;
+	if mode == CacheMode::Disabled {
+		let (quartads, len, _) = corpus::read_paths(&paths, format, &position_map)?;
+		return Ok((quartads, len));
+	}
+
+	let key = corpus_key(&paths, format, &position_map)?;
+	let cache_file = dir.join(format!("{:016x}.quartads", key));
+	if mode == CacheMode::Use {
+		if let Ok(cached) = load(&cache_file) {
+			return Ok(cached);
+		}
+	}
+
+	let (quartads, len, _) = corpus::read_paths(&paths, format, &position_map)?;
+	if let Err(e) = store(&cache_file, &quartads, len) {
+		eprintln!("Warning: could not write cache {}: {}", cache_file.display(), e);
+	}
+
+	Ok((quartads, len))
+}
+
+// Hashes the canonical path, size and modification time of every file in
+// order, together with the corpus format and the set of keys that quartads are
+// restricted to. Reading the metadata is much cheaper than reading a large
+// corpus; only where a file has no modification time is its content hashed.
+fn corpus_key(paths: &[PathBuf], format: &CorpusFormat, position_map: &LayoutPosMap)
+-> Result<u64>
+{
+	let mut hash = Fnv(FNV_OFFSET);
+	hash.write_u64(CACHE_VERSION as u64);
+	hash.write(format.describe().as_bytes());
+	for c in 0..128u8 {
+		if position_map.get_key_position(c as char).is_some() {
+			hash.write(&[c]);
+		}
+	}
+
+	for path in paths {
+		let canonical = fs::canonicalize(path).map_err(|e| Error::io(e, path))?;
+		let metadata = fs::metadata(&canonical).map_err(|e| Error::io(e, path))?;
+		hash.write(canonical.to_string_lossy().as_bytes());
+		hash.write(&[0]);
+		hash.write_u64(metadata.len());
+		match metadata.modified().ok().and_then(|t| t.duration_since(UNIX_EPOCH).ok()) {
+			Some(mtime) => {
+				hash.write_u64(mtime.as_secs());
+				hash.write_u64(mtime.subsec_nanos() as u64);
+			},
+			None => hash_contents(&mut hash, path)?,
+		}
+	}
+
+	Ok(hash.0)
+}
+
+fn hash_contents(hash: &mut Fnv, path: &Path)
+-> Result<()>
+{
+	let mut file = File::open(path).map_err(|e| Error::io(e, path))?;
+	let mut buf = vec![0u8; 1 << 16];
+	loop {
+		let n = file.read(&mut buf).map_err(|e| Error::io(e, path))?;
+		if n == 0 {
+			return Ok(());
+		}
+		hash.write(&buf[..n]);
+	}
+}
+
+// File format (all integers little-endian):
+//   magic "KGQC", version: u32, corpus length: u64, quartad count: u64,
+//   then per quartad: key length: u8, key bytes, count: u64.
+fn load(path: &Path)
+-> io::Result<(QuartadList, usize)>
+{
+	let mut r = BufReader::new(File::open(path)?);
+
+	let mut magic = [0u8; 4];
+	r.read_exact(&mut magic)?;
+	if &magic != CACHE_MAGIC || read_u32(&mut r)? != CACHE_VERSION {
+		return Err(io::Error::new(io::ErrorKind::InvalidData, "not a quartad cache"));
+	}
+
+	let len = read_u64(&mut r)? as usize;
+	let count = read_u64(&mut r)? as usize;
+	let mut quartads = HashMap::with_capacity(count);
+	for _ in 0..count {
+		let mut key_len = [0u8; 1];
+		r.read_exact(&mut key_len)?;
+		let mut key = vec![0u8; key_len[0] as usize];
+		r.read_exact(&mut key)?;
+		let key = String::from_utf8(key)
+			.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+		quartads.insert(key, read_u64(&mut r)? as usize);
+	}
+
+	Ok((QuartadList(quartads), len))
+}
+
+fn store(path: &Path, quartads: &QuartadList, len: usize)
+-> io::Result<()>
+{
+	if let Some(dir) = path.parent() {
+		fs::create_dir_all(dir)?;
+	}
+
+	// Write to a temporary file first so that a concurrent or interrupted run
+	// never sees a partial cache.
+	let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+	{
+		let QuartadList(ref map) = *quartads;
+		let mut w = BufWriter::new(File::create(&tmp)?);
+		w.write_all(CACHE_MAGIC)?;
+		w.write_all(&CACHE_VERSION.to_le_bytes())?;
+		w.write_all(&(len as u64).to_le_bytes())?;
+		w.write_all(&(map.len() as u64).to_le_bytes())?;
+		for (key, count) in map {
+			w.write_all(&[key.len() as u8])?;
+			w.write_all(key.as_bytes())?;
+			w.write_all(&(*count as u64).to_le_bytes())?;
+		}
+		w.flush()?;
+	}
+
+	fs::rename(&tmp, path)
+}
+
+fn read_u32<R: Read>(r: &mut R)
+-> io::Result<u32>
+{
+	let mut buf = [0u8; 4];
+	r.read_exact(&mut buf)?;
+	Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(r: &mut R)
+-> io::Result<u64>
+{
+	let mut buf = [0u8; 8];
+	r.read_exact(&mut buf)?;
+	Ok(u64::from_le_bytes(buf))
+}
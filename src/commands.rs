@@ -0,0 +1,161 @@
+//! The commands that the `keygen` program runs, taking options that have
+//! already been parsed.
+
+extern crate rand;
+
+use std::path::Path;
+
+use keygen;
+use keygen::CorpusFormat;
+use keygen::Error;
+use keygen::ExportFormat;
+use keygen::ExportOptions;
+use keygen::Json;
+use keygen::Layout;
+use keygen::LayoutShuffleMask;
+use keygen::NamedLayout;
+use keygen::Output;
+use keygen::OutputFormat;
+use keygen::Problem;
+use keygen::QuartadList;
+use keygen::Result;
+use keygen::Scored;
+use keygen::Strategy;
+
+// The layouts that `command` uses, given by `specs`. `analyze` scores any
+// number of them, by default all the built-in ones; `run-ref` scores the
+// built-in ones and any others given; the other commands use one, by default
+// the initial layout.
+pub fn find_layouts(command: &str, specs: &[String], dir: &Path)
+-> Result<Vec<NamedLayout>>
+{
+	let builtin_names = keygen::builtin_layout_names();
+	let specs: Vec<String> = match command {
+		"analyze" if specs.is_empty() => builtin_names,
+		"analyze" | "compare" => specs.to_vec(),
+		"run-ref" => builtin_names.into_iter().chain(specs.iter().cloned()).collect(),
+		_ => vec![specs.first().cloned().unwrap_or_else(|| "init".to_string())],
+	};
+	let layouts: Vec<NamedLayout> = specs.iter()
+		.map(|spec| keygen::find_layout(spec, dir))
+		.collect::<Result<_>>()?;
+	if command == "compare" && layouts.len() < 2 {
+		return Err(Error::config("compare needs at least two layouts"));
+	}
+	Ok(layouts)
+}
+
+// Runs `strategy` from `layout` again and again, each time with the next
+// seed, printing the best layouts of each run.
+#[allow(clippy::too_many_arguments)]
+pub fn run(quartads: &QuartadList, len: usize, layout: &Layout, mask: &LayoutShuffleMask, debug: bool, top: usize, strategy: &Strategy, swaps: usize, min_distance: usize, seed: Option<u64>, output: &Output)
+{
+	let penalties = keygen::penalties();
+	let problem = Problem { quartads, len, mask, penalties: &penalties };
+
+	// Each run has its own seed, so that any one of them can be repeated.
+	let mut seed = seed.unwrap_or_else(rand::random);
+	loop {
+		let mut optimizer = strategy.optimizer(problem, top, min_distance, swaps, seed);
+		keygen::simulate(&mut *optimizer, &problem, layout, debug, seed, output);
+		seed = seed.wrapping_add(1);
+	}
+}
+
+pub fn analyze(quartads: &QuartadList, len: usize, layouts: &[NamedLayout], output: &Output)
+{
+	let penalties = keygen::penalties();
+
+	let results: Vec<_> = layouts.iter()
+		.map(|l| keygen::calculate_penalty(quartads, len, &l.layout, &penalties, true))
+		.collect();
+	if !output.is_text() {
+		let scored: Vec<Scored> = layouts.iter().zip(results.iter())
+			.map(|(l, penalty)| Scored {
+				name: l.name.clone(),
+				layout: &l.layout,
+				penalty,
+			})
+			.collect();
+		output.print_results(vec![], &scored);
+		return;
+	}
+
+	for (i, (l, penalty)) in layouts.iter().zip(results.iter()).enumerate() {
+		if i > 0 {
+			println!();
+		}
+		println!("Layout: {}", l.name);
+		keygen::print_result(&l.layout, penalty);
+	}
+}
+
+pub fn compare(quartads: &QuartadList, len: usize, layouts: &[NamedLayout], top: usize, output: &Output)
+{
+	let penalties = keygen::penalties();
+
+	let names: Vec<String> = layouts.iter().map(|l| l.name.clone()).collect();
+	let results: Vec<_> = layouts.iter()
+		.map(|l| keygen::calculate_penalty(quartads, len, &l.layout, &penalties, true))
+		.collect();
+	let layouts: Vec<&Layout> = layouts.iter().map(|l| &l.layout).collect();
+	keygen::output_comparison(output, &names, &layouts, &results, top);
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn refine(quartads: &QuartadList, len: usize, layout: &Layout, mask: &LayoutShuffleMask, debug: bool, top: usize, swaps: usize, min_distance: usize, output: &Output)
+{
+	let penalties = keygen::penalties();
+	let problem = Problem { quartads, len, mask, penalties: &penalties };
+
+	keygen::refine(&problem, layout, debug, top, swaps, min_distance, output);
+}
+
+pub fn list_layouts(output: &Output, layouts_dir: &Path)
+{
+	let names = keygen::layout_names(layouts_dir);
+	match output.format {
+		OutputFormat::Text => for name in names {
+			println!("{}", name);
+		},
+		OutputFormat::Json => output.print_json(vec![
+			("layouts", Json::Arr(names.into_iter().map(Json::from).collect())),
+		]),
+		OutputFormat::Csv => output.print_csv(&["name".to_string()], &names.into_iter().map(|n| vec![n]).collect::<Vec<_>>()),
+	}
+}
+
+pub fn corpus_stats(corpus_filename: &str, format: &CorpusFormat, layout: &Layout, top: usize, output: &Output)
+-> Result<()>
+{
+	let (stats, files) = keygen::read_corpus_stats(corpus_filename, format, layout)?;
+	if output.is_text() {
+		println!("Corpus: {} ({} files)", corpus_filename, files);
+	}
+	keygen::output_corpus_stats(output, &stats, files, top);
+	Ok(())
+}
+
+// Exports the layout given by `spec` in `format`, by default named after it.
+// `matrix` is a physical key order as taken by `parse_matrix`. A file
+// gets the encoding that the format needs, where stdout gets UTF-8.
+pub fn export(spec: &str, layouts_dir: &Path, format: ExportFormat, name: Option<String>, matrix: Option<String>, layout_macro: Option<String>, to_file: bool)
+-> Result<Vec<u8>>
+{
+	let NamedLayout { name: layout_name, layout, .. } = keygen::find_layout(spec, layouts_dir)?;
+
+	let mut options = ExportOptions::new(&name.unwrap_or(layout_name));
+	if let Some(spec) = matrix {
+		options.matrix = keygen::parse_matrix(&spec)?;
+	}
+	if let Some(layout_macro) = layout_macro {
+		options.layout_macro = layout_macro;
+	}
+
+	let output = keygen::export(&layout, format, &options);
+	if to_file {
+		Ok(keygen::encode_export(format, &output))
+	} else {
+		Ok(output.into_bytes())
+	}
+}
@@ -0,0 +1,196 @@
+//! Side-by-side comparison of the scores of several layouts.
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+use layout;
+use layout::Layout;
+use output;
+use output::Json;
+use output::Output;
+use output::OutputFormat;
+use output::Scored;
+use penalty::KeyPenaltyResult;
+
+pub struct NgramChange
+{
+	pub penalty: &'static str,
+	pub ngram:   String,
+	pub before:  f64,
+	pub after:   f64,
+}
+
+impl NgramChange
+{
+	pub fn difference(&self)
+	-> f64
+	{
+		self.after - self.before
+	}
+}
+
+// The (penalty, n-gram) pairs whose cost differs most between two detailed
+// results of the same penalties, largest change first.
+pub fn ngram_changes(before: &[KeyPenaltyResult], after: &[KeyPenaltyResult], top: usize)
+-> Vec<NgramChange>
+{
+	let mut changes = Vec::new();
+	for (b, a) in before.iter().zip(after.iter()) {
+		let ngrams: HashSet<&String> = b.high_keys.keys().chain(a.high_keys.keys()).collect();
+		for ngram in ngrams {
+			let change = NgramChange {
+				penalty: b.name,
+				ngram:   ngram.clone(),
+				before:  *b.high_keys.get(ngram).unwrap_or(&0.0),
+				after:   *a.high_keys.get(ngram).unwrap_or(&0.0),
+			};
+			if change.difference() != 0.0 {
+				changes.push(change);
+			}
+		}
+	}
+
+	// Break ties by name so that the output doesn't depend on hash order.
+	changes.sort_by(|x, y|
+		match y.difference().abs().partial_cmp(&x.difference().abs()) {
+			Some(Ordering::Equal) | None => (x.penalty, &x.ngram).cmp(&(y.penalty, &y.ngram)),
+			Some(c) => c,
+		});
+	changes.truncate(top);
+	changes
+}
+
+// Relative difference in percent, if it is defined.
+pub fn relative(before: f64, after: f64)
+-> Option<f64>
+{
+	if before == 0.0 {
+		None
+	} else {
+		Some((after - before) / before.abs() * 100.0)
+	}
+}
+
+// Prints the totals of each penalty for every layout, with the difference of
+// each layout from the first, then the n-grams that changed most.
+pub fn print_comparison(names: &[String], results: &[(f64, f64, Vec<KeyPenaltyResult>)], top: usize)
+{
+	let base = &results[0];
+	let rule_width = base.2.iter().map(|p| p.name.len()).max().unwrap_or(0).max(6);
+	let width = names.iter().map(|n| n.chars().count()).max().unwrap_or(0).max(12);
+
+	println!("Differences are against {}.", names[0]);
+	println!();
+	let mut header = format!("{:<w$}", "", w = rule_width);
+	for (i, name) in names.iter().enumerate() {
+		header.push_str(&format!("  {:>w$}", name, w = width));
+		if i > 0 {
+			header.push_str(&format!("  {:>12}  {:>8}", "diff", "diff %"));
+		}
+	}
+	println!("{}", header);
+
+	let row = |label: &str, values: &[f64], precision: usize| {
+		let mut line = format!("{:<w$}", label, w = rule_width);
+		for (i, value) in values.iter().enumerate() {
+			line.push_str(&format!("  {:>w$.p$}", value, w = width, p = precision));
+			if i > 0 {
+				line.push_str(&format!("  {:>+12.p$}", value - values[0], p = precision));
+				line.push_str(&match relative(values[0], *value) {
+					Some(r) => format!("  {:>+7.2}%", r),
+					None => format!("  {:>8}", "-"),
+				});
+			}
+		}
+		println!("{}", line);
+	};
+
+	row("total", &results.iter().map(|r| r.0).collect::<Vec<_>>(), 1);
+	row("scaled", &results.iter().map(|r| r.1).collect::<Vec<_>>(), 4);
+	for (i, penalty) in base.2.iter().enumerate() {
+		row(penalty.name, &results.iter().map(|r| r.2[i].total).collect::<Vec<_>>(), 1);
+	}
+
+	for (name, result) in names.iter().zip(results.iter()).skip(1) {
+		println!();
+		println!("N-grams whose cost changed most, {} against {}:", name, names[0]);
+		println!("  {:<w$}  {:<8} {:>12} {:>12} {:>12}", "rule", "n-gram", names[0], name, "diff", w = rule_width);
+		for change in ngram_changes(&base.2, &result.2, top) {
+			println!("  {:<w$}  {:<8} {:>12.1} {:>12.1} {:>+12.1}",
+				change.penalty, format!("{:?}", layout::key_labels(&change.ngram)),
+				change.before, change.after, change.difference(), w = rule_width);
+		}
+	}
+}
+
+// Prints the same comparison as JSON or CSV.
+pub fn output_comparison(output: &Output, names: &[String], layouts: &[&Layout], results: &[output::Penalty], top: usize)
+{
+	let base = &results[0];
+	let diff = |before: f64, after: f64| Json::obj(vec![
+		("diff", Json::from(after - before)),
+		("percent", Json::from(relative(before, after))),
+	]);
+
+	match output.format {
+		OutputFormat::Text => print_comparison(names, results, top),
+		OutputFormat::Json => {
+			let scored: Vec<Json> = names.iter().zip(layouts.iter()).zip(results.iter())
+				.map(|((name, layout), penalty)| output::result_json(&Scored {
+					name: name.clone(),
+					layout,
+					penalty,
+				}))
+				.collect();
+			let differences: Vec<Json> = names.iter().zip(results.iter()).skip(1)
+				.map(|(name, result)| {
+					let penalties = base.2.iter().zip(result.2.iter())
+						.map(|(b, a)| Json::obj(vec![
+							("name", Json::from(b.name)),
+							("total", diff(b.total, a.total)),
+						]))
+						.collect();
+					let ngrams = ngram_changes(&base.2, &result.2, top).into_iter()
+						.map(|c| Json::obj(vec![
+							("penalty", Json::from(c.penalty)),
+							("keys", Json::from(layout::key_labels(&c.ngram))),
+							("before", Json::from(c.before)),
+							("after", Json::from(c.after)),
+							("diff", Json::from(c.difference())),
+						]))
+						.collect();
+					Json::obj(vec![
+						("name", Json::from(&name[..])),
+						("total", diff(base.0, result.0)),
+						("scaled", diff(base.1, result.1)),
+						("penalties", Json::Arr(penalties)),
+						("ngrams", Json::Arr(ngrams)),
+					])
+				})
+				.collect();
+			output.print_json(vec![
+				("baseline", Json::from(&names[0][..])),
+				("layouts", Json::Arr(scored)),
+				("differences", Json::Arr(differences)),
+			]);
+		},
+		OutputFormat::Csv => {
+			let header: Vec<String> = ["layout", "rule", "value", "diff", "diff_percent"].iter().map(|s| s.to_string()).collect();
+			let mut rows = Vec::new();
+			for (name, result) in names.iter().zip(results.iter()) {
+				let mut values = vec![("total", base.0, result.0), ("scaled", base.1, result.1)];
+				values.extend(base.2.iter().zip(result.2.iter()).map(|(b, a)| (b.name, b.total, a.total)));
+				for (rule, before, after) in values {
+					rows.push(vec![
+						name.clone(),
+						rule.to_string(),
+						after.to_string(),
+						(after - before).to_string(),
+						relative(before, after).map(|r| r.to_string()).unwrap_or_default(),
+					]);
+				}
+			}
+			output.print_csv(&header, &rows);
+		},
+	}
+}
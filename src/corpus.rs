@@ -0,0 +1,295 @@
+//! Reading corpora from files, directories and globs of files. Corpora are
+//! streamed in chunks, so their size is not bounded by memory.
+
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use std::io::Read;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str;
+
+use error::Error;
+use error::Result;
+use keylog;
+use layout::LayoutPosMap;
+use penalty::QuartadCounter;
+use penalty::QuartadList;
+use wordlist;
+use wordlist::WordListConfig;
+
+const CHUNK_SIZE: usize = 1 << 16;
+
+#[derive(Clone)]
+pub enum CorpusFormat
+{
+	// Running text.
+	Text,
+	// `word count` lines.
+	Words(WordListConfig),
+	// One key event per line, including keys that don't type a character.
+	KeyLog,
+}
+
+impl CorpusFormat
+{
+	// Identifies the format and its settings, for use in cache keys.
+	pub fn describe(&self)
+	-> String
+	{
+		match *self {
+			CorpusFormat::Text => "text".to_string(),
+			CorpusFormat::Words(ref config) => format!("words {}", config.describe()),
+			CorpusFormat::KeyLog => "keylog".to_string(),
+		}
+	}
+}
+
+// The corpus format named `name`, by default plain text. A words corpus puts
+// `word_sep`, by default a space, between words, and follows them with the
+// punctuation in `punct`, each given as MARK:P.
+pub fn parse_format(name: Option<&str>, word_sep: Option<String>, punct: &[String])
+-> Result<CorpusFormat>
+{
+	match name {
+		None | Some("text") => Ok(CorpusFormat::Text),
+		Some("words") => {
+			let mut config = WordListConfig::default();
+			if let Some(sep) = word_sep {
+				config.separator = sep;
+			}
+			for punct in punct {
+				config.add_punctuation(punct)?;
+			}
+			Ok(CorpusFormat::Words(config))
+		},
+		Some("keylog") => Ok(CorpusFormat::KeyLog),
+		Some(f) => Err(Error::config(format!("unknown corpus format {}", f))),
+	}
+}
+
+// Expands a corpus argument into the list of files it names. The argument may
+// be a file, a directory (read recursively) or a glob where `*` and `?` may
+// appear in any path component.
+pub fn expand_paths(spec: &str)
+-> Result<Vec<PathBuf>>
+{
+	let mut paths = Vec::new();
+	if spec.contains('*') || spec.contains('?') {
+		for path in glob(spec).map_err(|e| Error::io(e, Path::new(spec)))? {
+			collect_files(&path, &mut paths)?;
+		}
+		if paths.is_empty() {
+			return Err(io::Error::new(io::ErrorKind::NotFound,
+				format!("no files match {}", spec)).into());
+		}
+	} else {
+		collect_files(Path::new(spec), &mut paths)?;
+	}
+
+	Ok(paths)
+}
+
+// Reads every file in `paths` and counts its quartads. Returns the quartads,
+// the total length of the corpus in bytes and the characters that were
+// dropped because they are not on the layout.
+pub fn read_paths(paths: &[PathBuf], format: &CorpusFormat, position_map: &LayoutPosMap)
+-> Result<(QuartadList, usize, HashMap<char, usize>)>
+{
+	match *format {
+		CorpusFormat::Text => {
+			let mut counter = QuartadCounter::new(position_map);
+			feed_paths(paths, &mut counter)?;
+			let dropped = counter.dropped().clone();
+			let (quartads, len) = counter.finish();
+			Ok((quartads, len, dropped))
+		},
+		CorpusFormat::Words(ref config) => {
+			let mut words = Vec::new();
+			for path in paths {
+				let file = File::open(path).map_err(|e| Error::io(e, path))?;
+				let name = path.display().to_string();
+				words.extend(wordlist::read_words(BufReader::new(file), &name)?);
+			}
+			Ok(wordlist::synthesise(&words, config, position_map))
+		},
+		CorpusFormat::KeyLog => {
+			let mut counter = QuartadCounter::new(position_map);
+			for path in paths {
+				let file = File::open(path).map_err(|e| Error::io(e, path))?;
+				let name = path.display().to_string();
+				keylog::read_keylog(BufReader::new(file), &name, &mut counter)?;
+				counter.end_run();
+			}
+			let dropped = counter.dropped().clone();
+			let (quartads, len) = counter.finish();
+			Ok((quartads, len, dropped))
+		},
+	}
+}
+
+// Feeds every file in `paths` to `counter`, breaking runs between files.
+pub fn feed_paths(paths: &[PathBuf], counter: &mut QuartadCounter)
+-> Result<()>
+{
+	for path in paths {
+		let file = File::open(path).map_err(|e| Error::io(e, path))?;
+		read_chunks(file, path, |chunk| counter.push_str(chunk))?;
+		counter.end_run();
+	}
+
+	Ok(())
+}
+
+// Calls `f` with successive UTF-8 chunks of `reader`, which reads `path`.
+// Multi-byte characters that straddle two reads are carried over to the next
+// chunk.
+pub fn read_chunks<R, F>(mut reader: R, path: &Path, mut f: F)
+-> Result<()>
+where R: Read, F: FnMut(&str)
+{
+	let mut buf = vec![0u8; CHUNK_SIZE];
+	let mut carry = 0;
+	// Line that the next chunk starts on, to say where invalid text is.
+	let mut line = 1;
+	loop {
+		let n = match reader.read(&mut buf[carry..]) {
+			Ok(n) => n,
+			Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+			Err(e) => return Err(Error::io(e, path)),
+		};
+		let end = carry + n;
+		if n == 0 {
+			if carry > 0 {
+				return Err(invalid_utf8(path, line));
+			}
+			return Ok(());
+		}
+
+		let valid = match str::from_utf8(&buf[..end]) {
+			Ok(s) => {
+				f(s);
+				end
+			},
+			Err(e) => {
+				let valid = e.valid_up_to();
+				let s = str::from_utf8(&buf[..valid]).unwrap();
+				// An error with a known length is a genuinely invalid
+				// sequence, not just a truncated one.
+				if e.error_len().is_some() {
+					return Err(invalid_utf8(path, line + s.matches('\n').count()));
+				}
+				f(s);
+				valid
+			},
+		};
+		line += buf[..valid].iter().filter(|&&b| b == b'\n').count();
+
+		buf.copy_within(valid..end, 0);
+		carry = end - valid;
+	}
+}
+
+fn collect_files(path: &Path, paths: &mut Vec<PathBuf>)
+-> Result<()>
+{
+	let metadata = fs::metadata(path).map_err(|e| Error::io(e, path))?;
+	if !metadata.is_dir() {
+		paths.push(path.to_path_buf());
+		return Ok(());
+	}
+
+	let mut entries = Vec::new();
+	for entry in fs::read_dir(path).map_err(|e| Error::io(e, path))? {
+		entries.push(entry.map_err(|e| Error::io(e, path))?.path());
+	}
+	entries.sort();
+	for entry in entries {
+		collect_files(&entry, paths)?;
+	}
+
+	Ok(())
+}
+
+fn glob(spec: &str)
+-> io::Result<Vec<PathBuf>>
+{
+	let mut matches = vec![PathBuf::new()];
+	for component in Path::new(spec).components() {
+		let component = component.as_os_str();
+		let pattern = component.to_string_lossy();
+		if !pattern.contains('*') && !pattern.contains('?') {
+			for m in matches.iter_mut() {
+				m.push(component);
+			}
+			continue;
+		}
+
+		let mut next = Vec::new();
+		for dir in matches {
+			let read_from = if dir.as_os_str().is_empty() { PathBuf::from(".") } else { dir.clone() };
+			let entries = match fs::read_dir(&read_from) {
+				Ok(entries) => entries,
+				Err(_) => continue,
+			};
+			let mut names = Vec::new();
+			for entry in entries {
+				let name = entry?.file_name();
+				if wildcard_match(&pattern, &name.to_string_lossy()) {
+					names.push(name);
+				}
+			}
+			names.sort();
+			for name in names {
+				next.push(dir.join(name));
+			}
+		}
+		matches = next;
+	}
+
+	Ok(matches.into_iter().filter(|m| m.exists()).collect())
+}
+
+fn wildcard_match(pattern: &str, name: &str)
+-> bool
+{
+	let pattern: Vec<char> = pattern.chars().collect();
+	let name: Vec<char> = name.chars().collect();
+
+	// Hidden files are only matched by patterns that start with a dot.
+	if name.first() == Some(&'.') && pattern.first() != Some(&'.') {
+		return false;
+	}
+
+	// Greedy matching with backtracking to the last star.
+	let (mut p, mut n) = (0, 0);
+	let mut star: Option<(usize, usize)> = None;
+	while n < name.len() {
+		if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+			p += 1;
+			n += 1;
+		} else if p < pattern.len() && pattern[p] == '*' {
+			star = Some((p, n));
+			p += 1;
+		} else if let Some((sp, sn)) = star {
+			p = sp + 1;
+			n = sn + 1;
+			star = Some((sp, sn + 1));
+		} else {
+			return false;
+		}
+	}
+	while p < pattern.len() && pattern[p] == '*' {
+		p += 1;
+	}
+
+	p == pattern.len()
+}
+
+fn invalid_utf8(path: &Path, line: usize)
+-> Error
+{
+	Error::corpus(&path.display().to_string(), line, "invalid UTF-8; corpora must be UTF-8 text".to_string())
+}
@@ -0,0 +1,217 @@
+//! The errors that keygen reports, and the exit codes they lead to.
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::result;
+
+// Exit codes, from BSD's sysexits.h.
+const EX_USAGE:   i32 = 64;
+const EX_DATAERR: i32 = 65;
+const EX_NOINPUT: i32 = 66;
+const EX_IOERR:   i32 = 74;
+
+#[derive(Debug)]
+pub enum Error
+{
+	// A file couldn't be read or written; `path` names it if it is known.
+	Io
+	{
+		path:  Option<String>,
+		error: io::Error,
+	},
+	// A corpus file has content that its format doesn't allow.
+	Corpus
+	{
+		path:    String,
+		line:    Option<usize>,
+		message: String,
+	},
+	// A layout couldn't be read; `path` names its file, if it came from one.
+	Layout
+	{
+		path:    Option<String>,
+		line:    Option<usize>,
+		message: String,
+	},
+	// Invalid options or configuration.
+	Config(String),
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+// What the layout readers report: what is wrong, and the line it is on
+// unless it concerns the layout as a whole.
+#[derive(Debug)]
+pub struct LayoutParseError
+{
+	pub line:    Option<usize>,
+	pub message: String,
+}
+
+impl LayoutParseError
+{
+	pub fn new<S: Into<String>>(message: S)
+	-> LayoutParseError
+	{
+		LayoutParseError {
+			line: None,
+			message: message.into(),
+		}
+	}
+
+	pub fn at_line<S: Into<String>>(line: usize, message: S)
+	-> LayoutParseError
+	{
+		LayoutParseError {
+			line: Some(line),
+			message: message.into(),
+		}
+	}
+}
+
+impl Error
+{
+	pub fn io(error: io::Error, path: &Path)
+	-> Error
+	{
+		Error::Io {
+			path: Some(path.display().to_string()),
+			error,
+		}
+	}
+
+	pub fn corpus(path: &str, line: usize, message: String)
+	-> Error
+	{
+		Error::Corpus {
+			path: path.to_string(),
+			line: Some(line),
+			message,
+		}
+	}
+
+	// An error reading line `line` of the corpus file `path`: text that isn't
+	// UTF-8 is a fault of the corpus, anything else one of the file.
+	pub fn corpus_line(error: io::Error, path: &str, line: usize)
+	-> Error
+	{
+		if error.kind() == io::ErrorKind::InvalidData {
+			Error::corpus(path, line, "invalid UTF-8; corpora must be UTF-8 text".to_string())
+		} else {
+			Error::Io {
+				path: Some(path.to_string()),
+				error,
+			}
+		}
+	}
+
+	pub fn config<S: Into<String>>(message: S)
+	-> Error
+	{
+		Error::Config(message.into())
+	}
+
+	// Names the file that the error is in, unless it already names one.
+	pub fn in_file(self, file: &Path)
+	-> Error
+	{
+		let file = Some(file.display().to_string());
+		match self {
+			Error::Io { path: None, error } => Error::Io { path: file, error },
+			Error::Layout { path: None, line, message } => Error::Layout { path: file, line, message },
+			e => e,
+		}
+	}
+
+	// The exit code of a program that stops with this error.
+	pub fn exit_code(&self)
+	-> i32
+	{
+		match *self {
+			Error::Io { ref error, .. } if error.kind() == io::ErrorKind::NotFound => EX_NOINPUT,
+			Error::Io { .. } => EX_IOERR,
+			Error::Corpus { .. } | Error::Layout { .. } => EX_DATAERR,
+			Error::Config(_) => EX_USAGE,
+		}
+	}
+}
+
+impl fmt::Display for Error
+{
+	fn fmt(&self, f: &mut fmt::Formatter)
+	-> fmt::Result
+	{
+		match *self {
+			Error::Io { path: Some(ref path), ref error } => write!(f, "{}: {}", path, error),
+			Error::Io { path: None, ref error } => write!(f, "{}", error),
+			Error::Corpus { ref path, line, ref message } => {
+				write!(f, "{}", path)?;
+				if let Some(line) = line {
+					write!(f, ":{}", line)?;
+				}
+				write!(f, ": {}", message)
+			},
+			Error::Layout { ref path, line, ref message } => {
+				match (path, line) {
+					(Some(path), Some(line)) => write!(f, "{}:{}: ", path, line)?,
+					(Some(path), None) => write!(f, "{}: ", path)?,
+					(None, Some(line)) => write!(f, "line {}: ", line)?,
+					(None, None) => (),
+				}
+				write!(f, "{}", message)
+			},
+			Error::Config(ref message) => write!(f, "{}", message),
+		}
+	}
+}
+
+impl fmt::Display for LayoutParseError
+{
+	fn fmt(&self, f: &mut fmt::Formatter)
+	-> fmt::Result
+	{
+		match self.line {
+			Some(line) => write!(f, "line {}: {}", line, self.message),
+			None => write!(f, "{}", self.message),
+		}
+	}
+}
+
+impl error::Error for Error
+{
+	fn source(&self)
+	-> Option<&(dyn error::Error + 'static)>
+	{
+		match *self {
+			Error::Io { ref error, .. } => Some(error),
+			_ => None,
+		}
+	}
+}
+
+impl From<io::Error> for Error
+{
+	fn from(error: io::Error)
+	-> Error
+	{
+		Error::Io {
+			path: None,
+			error,
+		}
+	}
+}
+
+impl From<LayoutParseError> for Error
+{
+	fn from(e: LayoutParseError)
+	-> Error
+	{
+		Error::Layout {
+			path: None,
+			line: e.line,
+			message: e.message,
+		}
+	}
+}
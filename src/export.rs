@@ -0,0 +1,221 @@
+//! Exporting layouts to keyboard firmware and operating system keymap formats.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use error::Error;
+use error::Result;
+use layout;
+use layout::Layout;
+use kanata;
+use keylayout;
+use klc;
+use layoutfile;
+use qmk;
+use xkb;
+use zmk;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ExportFormat
+{
+	Qmk,
+	Zmk,
+	Kanata,
+	KMonad,
+	Xkb,
+	Klc,
+	KeyLayout,
+	LayoutFile,
+}
+
+pub struct ExportOptions
+{
+	// Name of the layout, used in comments and identifiers.
+	pub name:   String,
+	// QMK and ZMK: for each key of the physical keyboard, in the order the
+	// firmware expects them, the `KeyMap` position that goes there, if any.
+	// Rows are only used to lay out the output.
+	pub matrix: Vec<Vec<Option<usize>>>,
+	// QMK: the layout macro of the keyboard.
+	pub layout_macro: String,
+}
+
+impl ExportOptions
+{
+	pub fn new(name: &str)
+	-> ExportOptions
+	{
+		ExportOptions {
+			name: name.to_string(),
+			matrix: vec![
+				(0..11).map(Some).collect(),
+				(11..22).map(Some).collect(),
+				(22..32).map(Some).collect(),
+				(32..34).map(Some).collect(),
+			],
+			layout_macro: "LAYOUT".to_string(),
+		}
+	}
+}
+
+pub fn parse_format(s: &str)
+-> Option<ExportFormat>
+{
+	match s {
+		"qmk" => Some(ExportFormat::Qmk),
+		"zmk" => Some(ExportFormat::Zmk),
+		"kanata" => Some(ExportFormat::Kanata),
+		"kmonad" => Some(ExportFormat::KMonad),
+		"xkb" => Some(ExportFormat::Xkb),
+		"klc" => Some(ExportFormat::Klc),
+		"keylayout" => Some(ExportFormat::KeyLayout),
+		"layout" => Some(ExportFormat::LayoutFile),
+		_ => None,
+	}
+}
+
+pub fn export(layout: &Layout, format: ExportFormat, options: &ExportOptions)
+-> String
+{
+	match format {
+		ExportFormat::Qmk => qmk::render(layout, options),
+		ExportFormat::Zmk => zmk::render(layout, options),
+		ExportFormat::Kanata => kanata::render(layout, kanata::Dialect::Kanata, options),
+		ExportFormat::KMonad => kanata::render(layout, kanata::Dialect::KMonad, options),
+		ExportFormat::Xkb => xkb::render(layout, options),
+		ExportFormat::Klc => klc::render(layout, options),
+		ExportFormat::KeyLayout => keylayout::render(layout, options),
+		ExportFormat::LayoutFile => layoutfile::render(layout, &options.name),
+	}
+}
+
+// The bytes to write for an export in `format`.
+pub fn encode(format: ExportFormat, s: &str)
+-> Vec<u8>
+{
+	match format {
+		ExportFormat::Klc => klc::encode(s),
+		_ => s.as_bytes().to_vec(),
+	}
+}
+
+// Reads a physical matrix: the `KeyMap` position of each physical key, in
+// firmware order, separated by whitespace or commas, with `_` for keys that
+// the layout doesn't use. Rows are separated by newlines or `;`. `spec` is
+// either such a list or a file holding one.
+pub fn parse_matrix(spec: &str)
+-> Result<Vec<Vec<Option<usize>>>>
+{
+	let mut contents = String::new();
+	let spec = if Path::new(spec).is_file() {
+		File::open(spec)
+			.and_then(|mut f| f.read_to_string(&mut contents))
+			.map_err(|e| Error::io(e, Path::new(spec)))?;
+		&contents[..]
+	} else {
+		spec
+	};
+
+	let mut matrix = Vec::new();
+	let mut seen = [false; 34];
+	for line in spec.split(&['\n', ';'][..]) {
+		let mut row = Vec::new();
+		for token in line.split(|c: char| c.is_whitespace() || c == ',').filter(|t| !t.is_empty()) {
+			if token == "_" {
+				row.push(None);
+				continue;
+			}
+			match token.parse::<usize>() {
+				Ok(pos) if pos < 34 && !seen[pos] => {
+					seen[pos] = true;
+					row.push(Some(pos));
+				},
+				Ok(pos) if pos < 34 => return Err(Error::config(format!("position {} appears twice in the matrix", pos))),
+				_ => return Err(Error::config(format!("invalid matrix position {:?}; expected 0-33 or _", token))),
+			}
+		}
+		if !row.is_empty() {
+			matrix.push(row);
+		}
+	}
+
+	if matrix.is_empty() {
+		return Err(Error::config("empty matrix"));
+	}
+	Ok(matrix)
+}
+
+// The key at each physical position, row by row, or `None` where the matrix
+// has a gap.
+pub fn physical_keys(keys: &[char; 34], options: &ExportOptions)
+-> Vec<Vec<Option<char>>>
+{
+	options.matrix.iter()
+		.map(|row| row.iter().map(|pos| pos.map(|p| keys[p])).collect())
+		.collect()
+}
+
+// Every `KeyMap` position that is on the physical keyboard.
+pub fn matrix_positions(options: &ExportOptions)
+-> Vec<usize>
+{
+	options.matrix.iter().flat_map(|row| row.iter().filter_map(|p| *p)).collect()
+}
+
+// The positions among `positions` whose upper key isn't what shift would
+// type on the lower key, and so need special handling to be typed.
+pub fn shift_overrides(layout: &Layout, positions: &[usize])
+-> Vec<usize>
+{
+	positions.iter().cloned().filter(|&pos| {
+		let lower = layout.lower()[pos];
+		let upper = layout.upper()[pos];
+		if lower == '\0' || upper == '\0' || layout::key_name(lower).is_some() {
+			return false;
+		}
+
+		// Symbols that are already shifted stay the same with shift held.
+		let natural = if layout::is_shifted(lower) { lower } else { layout::shifted(lower) };
+		upper != natural
+	}).collect()
+}
+
+// Both layers of the layout as comment lines, each starting with `prefix`.
+pub fn diagram(layout: &Layout, prefix: &str)
+-> String
+{
+	let mut s = String::new();
+	for (name, keys) in [("lower", layout.lower()), ("upper", layout.upper())].iter() {
+		let k: Vec<char> = keys.iter()
+			.map(|kc| if *kc == '\0' { ' ' } else { layout::key_label(*kc) })
+			.collect();
+		let lines = [
+			format!("{}:", name),
+			format!("  {} {} {} {} {}   {} {} {} {} {} {}", k[0], k[1], k[2], k[3], k[4], k[5], k[6], k[7], k[8], k[9], k[10]),
+			format!("  {} {} {} {} {}   {} {} {} {} {} {}", k[11], k[12], k[13], k[14], k[15], k[16], k[17], k[18], k[19], k[20], k[21]),
+			format!("  {} {} {} {} {}   {} {} {} {} {}", k[22], k[23], k[24], k[25], k[26], k[27], k[28], k[29], k[30], k[31]),
+			format!("          {}   {}", k[32], k[33]),
+		];
+		// Keys such as space are blank, so trim what they leave at the end.
+		s.push_str(&format!("{}\n", prefix.trim_end()));
+		for line in lines.iter() {
+			s.push_str(format!("{}{}", prefix, line).trim_end());
+			s.push('\n');
+		}
+	}
+	s
+}
+
+// Turns a layout name into something usable as an identifier.
+pub fn identifier(name: &str)
+-> String
+{
+	let id: String = name.chars()
+		.map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+		.collect();
+	match id.chars().next() {
+		Some(c) if c.is_ascii_alphabetic() => id,
+		_ => format!("l_{}", id),
+	}
+}
@@ -0,0 +1,197 @@
+//! A genetic algorithm: a population of layouts that breeds the next
+//! generation by crossing over layouts picked by tournament and mutating the
+//! children with a few swaps, keeping the best layouts of each generation as
+//! they are.
+
+extern crate rand;
+
+use std::cmp::Ordering;
+use std::f64;
+use self::rand::Rng;
+use self::rand::StdRng;
+
+use best::BestLayouts;
+use layout::Layout;
+use layout::LayoutShuffleMask;
+use optimizer;
+use optimizer::Optimizer;
+use optimizer::Problem;
+use optimizer::Progress;
+
+// The number of layouts in each generation, and the number of generations.
+pub const POPULATION:  usize = 50;
+pub const GENERATIONS: usize = 200;
+
+// How many layouts each tournament picks the best of, how many of the best
+// layouts go on to the next generation unchanged, and the chance that a child
+// is mutated.
+pub const TOURNAMENT: usize = 3;
+pub const ELITES:     usize = 2;
+pub const MUTATION:   f64 = 0.5;
+
+// Order crossover: the child has the keys of `a` on a random run of the
+// positions that may be swapped, and the rest of the keys in the order they
+// come in `b`, so that it has the same keys as both. Pinned positions keep
+// their keys, which are the same in both.
+pub fn crossover<R: Rng>(a: &Layout, b: &Layout, mask: &LayoutShuffleMask, rng: &mut R)
+-> Layout
+{
+	let positions = mask.positions();
+	let n = positions.len();
+	if n < 2 {
+		return a.clone();
+	}
+	let start = rng.gen::<usize>() % n;
+	let end = start + 1 + rng.gen::<usize>() % (n - 1);
+
+	let mut lower = *a.lower();
+	let mut upper = *a.upper();
+
+	// The keys that `a` gives the child, which `b` then doesn't.
+	let mut taken: Vec<(char, char)> = (start..end)
+		.map(|k| positions[k % n])
+		.map(|p| (lower[p], upper[p]))
+		.collect();
+
+	let mut from_b = (0..n)
+		.map(|k| positions[(end + k) % n])
+		.map(|p| (b.lower()[p], b.upper()[p]))
+		.filter(|key| match taken.iter().position(|t| t == key) {
+			Some(i) => {
+				taken.swap_remove(i);
+				false
+			},
+			None => true,
+		});
+	for k in end..start + n {
+		let p = positions[k % n];
+		let (l, u) = from_b.next().unwrap();
+		lower[p] = l;
+		upper[p] = u;
+	}
+
+	Layout::new(lower, upper)
+}
+
+// Mutates a child by `Layout::shuffle`, with probability `MUTATION`.
+pub fn mutate<R: Rng>(layout: &mut Layout, num_swaps: usize, mask: &LayoutShuffleMask, rng: &mut R)
+{
+	if rng.gen::<f64>() < MUTATION {
+		let times = rng.gen::<usize>() % num_swaps + 1;
+		layout.shuffle(times, mask, rng);
+	}
+}
+
+// The best of `TOURNAMENT` layouts picked at random from `population`.
+pub fn tournament<'a, R: Rng>(population: &'a [(Layout, f64)], rng: &mut R)
+-> &'a Layout
+{
+	let mut best = &population[rng.gen::<usize>() % population.len()];
+	for _ in 1..TOURNAMENT {
+		let other = &population[rng.gen::<usize>() % population.len()];
+		if other.1 < best.1 {
+			best = other;
+		}
+	}
+	&best.0
+}
+
+// Each step breeds a generation; the first is the starting layout and random
+// shuffles of it.
+pub struct Genetic<'a>
+{
+	problem:     Problem<'a>,
+	population:  usize,
+	generations: usize,
+	num_swaps:   usize,
+	rng:         StdRng,
+	best:        BestLayouts,
+	// The current generation, best first.
+	curr:        Vec<(Layout, f64)>,
+	generation:  usize,
+}
+
+impl <'a> Genetic<'a>
+{
+	pub fn new(problem: Problem<'a>, top_layouts: usize, min_distance: usize, population: usize, generations: usize, num_swaps: usize, seed: u64)
+	-> Genetic<'a>
+	{
+		Genetic {
+			problem,
+			population,
+			generations,
+			num_swaps,
+			rng: optimizer::seeded_rng(seed),
+			best: BestLayouts::with_min_distance(top_layouts, min_distance),
+			curr: Vec::with_capacity(population),
+			generation: 0,
+		}
+	}
+}
+
+impl <'a> Optimizer for Genetic<'a>
+{
+	fn name(&self)
+	-> &'static str
+	{
+		"genetic"
+	}
+
+	fn init(&mut self, layout: &Layout, penalty: f64)
+	{
+		let shuffles = self.problem.mask.positions().len();
+		self.curr.clear();
+		self.curr.push((layout.clone(), penalty));
+		while self.curr.len() < self.population {
+			let mut layout = layout.clone();
+			layout.shuffle(shuffles, self.problem.mask, &mut self.rng);
+			let penalty = self.problem.penalty(&layout);
+			self.curr.push((layout, penalty));
+		}
+		self.curr.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal));
+		for &(ref layout, penalty) in self.curr.iter() {
+			self.best.insert(layout.clone(), penalty);
+		}
+		self.generation = 0;
+	}
+
+	fn step(&mut self)
+	-> bool
+	{
+		if self.generation >= self.generations {
+			return false;
+		}
+		self.generation += 1;
+
+		let mut next: Vec<(Layout, f64)> = self.curr.iter().take(ELITES).cloned().collect();
+		while next.len() < self.population {
+			let mut child = {
+				let a = tournament(&self.curr, &mut self.rng);
+				let b = tournament(&self.curr, &mut self.rng);
+				crossover(a, b, self.problem.mask, &mut self.rng)
+			};
+			mutate(&mut child, self.num_swaps, self.problem.mask, &mut self.rng);
+			let penalty = self.problem.penalty(&child);
+			self.best.insert(child.clone(), penalty);
+			next.push((child, penalty));
+		}
+		next.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal));
+		self.curr = next;
+		true
+	}
+
+	fn best(&self)
+	-> &BestLayouts
+	{
+		&self.best
+	}
+
+	fn progress(&self)
+	-> Progress
+	{
+		Progress {
+			step:    self.generation,
+			penalty: self.curr.first().map_or(f64::NAN, |c| c.1),
+		}
+	}
+}
@@ -0,0 +1,220 @@
+//! Hill climbing: each step moves to the best layout within a few swaps of
+//! the current one, until none is better.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::f64;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering as AtomicOrdering;
+use std::thread;
+
+use best::BestLayouts;
+use layout::Layout;
+use layout::SwapNeighbourhood;
+use optimizer::Optimizer;
+use optimizer::Problem;
+use optimizer::Progress;
+use penalty::PenaltyIndex;
+
+pub struct HillClimb<'a>
+{
+	problem:      Problem<'a>,
+	top_layouts:  usize,
+	min_distance: usize,
+	num_swaps:    usize,
+	best:         BestLayouts,
+	current:      Option<(Layout, f64)>,
+	round:        usize,
+	finished:     bool,
+}
+
+impl <'a> HillClimb<'a>
+{
+	pub fn new(problem: Problem<'a>, top_layouts: usize, min_distance: usize, num_swaps: usize)
+	-> HillClimb<'a>
+	{
+		HillClimb {
+			problem,
+			top_layouts,
+			min_distance,
+			num_swaps,
+			best: BestLayouts::with_min_distance(top_layouts, min_distance),
+			current: None,
+			round: 0,
+			finished: false,
+		}
+	}
+
+	// The layout it has got to, which is the best it has found.
+	pub fn current(&self)
+	-> Option<&Layout>
+	{
+		self.current.as_ref().map(|c| &c.0)
+	}
+}
+
+impl <'a> Optimizer for HillClimb<'a>
+{
+	fn name(&self)
+	-> &'static str
+	{
+		"refine"
+	}
+
+	fn init(&mut self, layout: &Layout, penalty: f64)
+	{
+		self.best = BestLayouts::with_min_distance(self.top_layouts, self.min_distance);
+		self.best.insert(layout.clone(), penalty);
+		self.current = Some((layout.clone(), penalty));
+		self.round = 0;
+		self.finished = false;
+	}
+
+	// Tests every layout within `num_swaps` swaps of the current layout. The
+	// step that finds none better is the last.
+	fn step(&mut self)
+	-> bool
+	{
+		if self.finished {
+			return false;
+		}
+		self.round += 1;
+
+		let (curr_layout, curr_penalty) = self.current.clone().expect("hill climbing before init");
+		let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+		let neighbours = best_neighbours(&self.problem, &curr_layout, self.top_layouts, self.num_swaps, self.min_distance, threads);
+
+		// The best neighbour is scored again in full, so that comparing
+		// penalties can't go round in circles.
+		let next = neighbours.best()
+			.map(|b| (b.0.clone(), self.problem.penalty(&b.0)))
+			.filter(|b| b.1 < curr_penalty);
+		for (layout, penalty) in neighbours.into_vec() {
+			self.best.insert(layout, penalty);
+		}
+		match next {
+			Some(next) => self.current = Some(next),
+			None => self.finished = true,
+		}
+		true
+	}
+
+	fn best(&self)
+	-> &BestLayouts
+	{
+		&self.best
+	}
+
+	fn progress(&self)
+	-> Progress
+	{
+		Progress {
+			step:    self.round,
+			penalty: self.current.as_ref().map_or(f64::NAN, |c| c.1),
+		}
+	}
+}
+
+// The best layouts within `num_swaps` swaps of `layout`. Each is scored by
+// how much moving its keys changes the penalty, on `threads` threads, which
+// take the layouts whose first swap starts at each position in turn. Unless the
+// layouts are kept a minimum distance apart, the result doesn't depend on how
+// many threads there are.
+fn best_neighbours(problem: &Problem, layout: &Layout, top_layouts: usize, num_swaps: usize, min_distance: usize, threads: usize)
+-> BestLayouts
+{
+	let index = PenaltyIndex::new(problem.quartads, layout);
+	let neighbourhood = SwapNeighbourhood::new(layout, problem.mask, num_swaps);
+	let len = problem.len as f64;
+
+	// Each thread takes the next start that no thread has taken yet, and
+	// returns its best layouts with where they come in the neighbourhood.
+	let next = AtomicUsize::new(0);
+	let mut found: Vec<(Layout, f64, (usize, usize))> = thread::scope(|s| {
+		let workers: Vec<_> = (0..threads).map(|_| {
+			let index = &index;
+			let neighbourhood = &neighbourhood;
+			let next = &next;
+			s.spawn(move || {
+				let mut best = BestLayouts::with_min_distance(top_layouts, min_distance);
+				let mut order = HashMap::new();
+				let mut cycle_changes: HashMap<Vec<usize>, f64> = HashMap::new();
+				let mut changes = Vec::new();
+				loop {
+					let first = next.fetch_add(1, AtomicOrdering::Relaxed);
+					if first >= neighbourhood.starts() {
+						break;
+					}
+					let mut i = 0;
+					neighbourhood.for_each_from(first, |cycles| {
+						changes.clear();
+						for cycle in cycles {
+							let change = match cycle_changes.get(&cycle[..]) {
+								Some(&change) => change,
+								None => {
+									let change = index.cycle_change(cycle);
+									cycle_changes.insert(cycle.clone(), change);
+									change
+								},
+							};
+							changes.push(change);
+						}
+						let penalty = (index.total() + index.change(cycles, &changes)) / len;
+						if best.admits(penalty) {
+							let candidate = layout.rearranged(cycles);
+							if best.insert(candidate.clone(), penalty) {
+								order.insert(candidate, (first, i));
+							}
+						}
+						i += 1;
+					});
+				}
+				best.into_vec().into_iter()
+					.map(|(l, penalty)| {
+						let i = order[&l];
+						(l, penalty, i)
+					})
+					.collect::<Vec<_>>()
+			})
+		}).collect();
+		workers.into_iter().flat_map(|w| w.join().unwrap()).collect()
+	});
+
+	// Merged in the order one thread would have found them.
+	found.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal).then(a.2.cmp(&b.2)));
+	let mut best = BestLayouts::with_min_distance(top_layouts, min_distance);
+	for (l, penalty, _) in found {
+		best.insert(l, penalty);
+	}
+	best
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	use layout;
+	use penalty;
+	use penalty::QuartadCounter;
+
+	#[test]
+	fn best_neighbours_do_not_depend_on_the_number_of_threads()
+	{
+		let position_map = layout::INIT_LAYOUT.get_position_map();
+		let mut counter = QuartadCounter::new(&position_map);
+		counter.push_str("Sphinx of black quartz, judge my vow; the five boxing wizards jump quickly.");
+		let (quartads, len) = counter.finish();
+		let penalties = penalty::init();
+		let problem = Problem { quartads: &quartads, len, mask: &layout::LAYOUT_MASK, penalties: &penalties };
+
+		let one = best_neighbours(&problem, &layout::INIT_LAYOUT, 20, 2, 0, 1).into_vec();
+		for threads in 2..5 {
+			let many = best_neighbours(&problem, &layout::INIT_LAYOUT, 20, 2, 0, threads).into_vec();
+			assert_eq!(many.len(), one.len());
+			for (a, b) in one.iter().zip(many.iter()) {
+				assert!(a.0 == b.0 && a.1 == b.1, "{} threads", threads);
+			}
+		}
+	}
+}
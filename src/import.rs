@@ -0,0 +1,236 @@
+//! Reading layouts from the formats that layouts are commonly shared in.
+//!
+//! Besides this program's own layout files and the legacy fixed-column
+//! format, a layout may be an XKB symbols file, keyboard-layout-editor JSON,
+//! or a plain grid of keys separated by spaces:
+//!
+//! ```text
+//! q w e r t   y u i o p
+//! a s d f g   h j k l ;
+//! z x c v b   n m , . /
+//! ```
+//!
+//! A grid may have an 11th key on the first two rows and a fourth row of
+//! thumb keys, and may be followed by a blank line and the upper layer in the
+//! same shape; otherwise the upper layer is derived using the US shift pairs.
+
+use error::Error;
+use error::LayoutParseError;
+use layout;
+use layout::Layout;
+use layout::LayoutShuffleMask;
+use layoutfile;
+use kle;
+use xkb;
+
+// Number of keys in each row of a layer, and where each row starts.
+static ROW_LENGTHS: [usize; 4] = [11, 11, 10, 2];
+static ROW_STARTS:  [usize; 4] = [0, 11, 22, 32];
+
+// Keys that layouts commonly leave out, and that go in these positions if
+// they aren't elsewhere on the layout.
+static DEFAULT_KEYS: [(usize, char); 3] = [(10, '-'), (21, '\''), (33, ' ')];
+
+// The keys found at each position by an importer, as (lower, upper).
+pub type ImportedKeys = [Option<(char, char)>; 34];
+
+// Reads a layout in any supported format, together with the positions that
+// the optimiser may not move.
+pub fn parse(s: &str)
+-> Result<(Layout, LayoutShuffleMask), Error>
+{
+	if layoutfile::is_layout_file(s) {
+		let file = layoutfile::parse(s)?;
+		return Ok((file.layout, file.mask));
+	}
+
+	let layout = if xkb::is_xkb(s) {
+		xkb::parse(s)
+	} else if kle::is_kle(s) {
+		kle::parse(s).and_then(|keys| complete(&keys))
+	} else if is_grid(s) {
+		parse_grid(s).and_then(|keys| complete(&keys))
+	} else if is_legacy(s) {
+		Ok(Layout::from_string(s))
+	} else {
+		Err(LayoutParseError::new("unrecognised layout format"))
+	};
+	Ok((layout?, layout::LAYOUT_MASK.clone()))
+}
+
+// Turns imported keys into a layout, filling in the keys that the format left
+// out. Like layout files, a key may only appear once per layer.
+pub fn complete(keys: &ImportedKeys)
+-> Result<Layout, LayoutParseError>
+{
+	let mut lower = ['\0'; 34];
+	let mut upper = ['\0'; 34];
+	for (pos, key) in keys.iter().enumerate() {
+		if let Some((l, u)) = *key {
+			if l != '\0' && lower.contains(&l) {
+				return Err(LayoutParseError::new(format!("duplicate key {:?} in the lower layer", layout::key_label(l))));
+			}
+			if u != '\0' && upper.contains(&u) {
+				return Err(LayoutParseError::new(format!("duplicate key {:?} in the upper layer", layout::key_label(u))));
+			}
+			lower[pos] = l;
+			upper[pos] = u;
+		}
+	}
+	for &(pos, kc) in DEFAULT_KEYS.iter() {
+		if keys[pos].is_none() && !lower.contains(&kc) && !upper.contains(&kc) {
+			lower[pos] = kc;
+			upper[pos] = layout::shifted(kc);
+		}
+	}
+	Ok(Layout::new(lower, upper))
+}
+
+// Whether `s` has the shape of the legacy format: the three rows of each
+// layer, 12, 12 and 13 characters long, where the space bar may have lost its
+// trailing space at the end of the file.
+fn is_legacy(s: &str)
+-> bool
+{
+	let lines: Vec<usize> = s.split('\n').take(7).map(|l| l.trim_end_matches('\r').chars().count()).collect();
+	match lines.len() {
+		6 | 7 => lines[..5] == [12, 12, 13, 12, 12] && (11..=13).contains(&lines[5]) &&
+			lines.get(6).is_none_or(|&n| n == 0),
+		_ => false,
+	}
+}
+
+fn is_grid(s: &str)
+-> bool
+{
+	content_lines(s).next()
+		.map(|line| keys_of(line).len() >= 10)
+		.unwrap_or(false)
+}
+
+fn parse_grid(s: &str)
+-> Result<ImportedKeys, LayoutParseError>
+{
+	// Blocks of rows, separated by blank lines.
+	let mut blocks: Vec<Vec<(usize, Vec<&str>)>> = vec![Vec::new()];
+	for (i, line) in s.lines().enumerate() {
+		if line.trim_start().starts_with("//") {
+			continue;
+		}
+		let tokens = keys_of(line);
+		if tokens.is_empty() {
+			if !blocks.last().unwrap().is_empty() {
+				blocks.push(Vec::new());
+			}
+		} else {
+			blocks.last_mut().unwrap().push((i + 1, tokens));
+		}
+	}
+	blocks.retain(|b| !b.is_empty());
+	if blocks.is_empty() || blocks.len() > 2 {
+		return Err(LayoutParseError::new(format!("expected one or two blocks of rows, found {}", blocks.len())));
+	}
+
+	let lower = grid_layer(&blocks[0])?;
+	let upper = match blocks.get(1) {
+		Some(block) => Some(grid_layer(block)?),
+		None => None,
+	};
+
+	let mut keys: ImportedKeys = [None; 34];
+	for pos in 0..34 {
+		if let Some(l) = lower[pos] {
+			let u = match upper {
+				Some(ref upper) => upper[pos].unwrap_or('\0'),
+				None => layout::shifted(l),
+			};
+			keys[pos] = Some((l, u));
+		}
+	}
+	Ok(keys)
+}
+
+fn grid_layer(rows: &[(usize, Vec<&str>)])
+-> Result<[Option<char>; 34], LayoutParseError>
+{
+	if rows.len() != 3 && rows.len() != 4 {
+		return Err(LayoutParseError::at_line(rows[0].0, format!("expected 3 or 4 rows, found {}", rows.len())));
+	}
+
+	let mut layer = [None; 34];
+	for (row, &(line_no, ref tokens)) in rows.iter().enumerate() {
+		let max = ROW_LENGTHS[row];
+		let min = if row == 3 { 1 } else { 10 };
+		if tokens.len() < min || tokens.len() > max {
+			return Err(LayoutParseError::at_line(line_no, format!("row {} has {} keys, expected {}", row + 1, tokens.len(),
+				if min == max { min.to_string() } else { format!("{} to {}", min, max) })));
+		}
+		// A single thumb key is the space bar's position.
+		let start = ROW_STARTS[row] + if row == 3 { max - tokens.len() } else { 0 };
+		for (i, token) in tokens.iter().enumerate() {
+			let kc = layoutfile::parse_key(token).map_err(|e| LayoutParseError::at_line(line_no, e))?;
+			layer[start + i] = Some(kc);
+		}
+	}
+	Ok(layer)
+}
+
+fn content_lines(s: &str)
+-> impl Iterator<Item = &str>
+{
+	s.lines().filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with("//"))
+}
+
+// The keys of a grid row; a lone `|` between the hands is ignored.
+fn keys_of(line: &str)
+-> Vec<&str>
+{
+	line.split_whitespace().filter(|t| *t != "|").collect()
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	static LEGACY: &str = "jcyfk|zl,uq=\nrsthd|mnaio'\n/vgpb|xw.;-e \nJCYFK|ZL<UQ+\nRSTHD|MNAIO\"\n?VGPB|XW>:_E \n";
+
+	#[test]
+	fn reads_the_legacy_format()
+	{
+		match parse(LEGACY) {
+			Ok((layout, _)) => assert_eq!(layout.lower(), layout::INIT_LAYOUT.lower()),
+			Err(e) => panic!("{}", e),
+		}
+	}
+
+	#[test]
+	fn rejects_an_unrecognised_format()
+	{
+		let keylayout = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<keyboard group=\"126\" id=\"-1\" name=\"x\">\n</keyboard>\n";
+		assert!(parse(keylayout).is_err());
+	}
+
+	#[test]
+	fn rejects_a_kle_position_that_is_not_a_number()
+	{
+		let kle = "[[\"q\",\"w\",\"e\",\"r\",\"t\",\"y\",\"u\",\"i\",\"o\",\"p\"],\n\
+		           [\"a\",\"s\",\"d\",\"f\",\"g\",\"h\",\"j\",\"k\",\"l\",\";\"],\n\
+		           [\"z\",\"x\",\"c\",\"v\",\"b\",\"n\",\"m\",\",\",\".\",\"/\"],\n\
+		           [{x:NaN},\" \",\" \"]]";
+		match parse(kle) {
+			Err(Error::Layout { line, .. }) => assert_eq!(line, Some(4)),
+			_ => panic!("expected a layout error"),
+		}
+	}
+
+	#[test]
+	fn reports_the_line_of_a_bad_row()
+	{
+		let grid = "q w e r t y u i o p\na s d f g h j k l ;\nz x c v b n m , . / - =\n";
+		match parse(grid) {
+			Err(Error::Layout { line, .. }) => assert_eq!(line, Some(3)),
+			_ => panic!("expected a layout error"),
+		}
+	}
+}
@@ -0,0 +1,173 @@
+//! Renders a layout as a Kanata or KMonad configuration.
+//!
+//! Both remap the keys of an ordinary keyboard, so the layout is placed on
+//! the ANSI keys that a QWERTY typist would use for each position: the letter
+//! block plus `[` and `'` for the outer pinky keys, left Alt and the space bar
+//! for the thumbs. Kanata's `fork` makes shift send the upper layer's symbol
+//! where it differs from a US keyboard; KMonad has no equivalent, so those
+//! symbols are listed in a comment instead.
+
+use layout;
+use layout::Layout;
+use export;
+use export::ExportOptions;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Dialect
+{
+	Kanata,
+	KMonad,
+}
+
+// The physical key at each `KeyMap` position.
+static SOURCE_KEYS: [&str; 34] = [
+	"q", "w", "e", "r", "t",   "y", "u", "i", "o", "p", "[",
+	"a", "s", "d", "f", "g",   "h", "j", "k", "l", ";", "'",
+	"z", "x", "c", "v", "b",   "n", "m", ",", ".", "/",
+	"lalt", "spc"];
+
+// Unshifted symbols, and the key that types each shifted symbol.
+static KEYS: [(char, &str); 43] = [
+	('-', "-"), ('=', "="), ('[', "["), (']', "]"), ('\\', "\\"),
+	(';', ";"), ('\'', "'"), ('`', "grv"), (',', ","), ('.', "."),
+	('/', "/"), (' ', "spc"),
+	('~', "grv"), ('!', "1"), ('@', "2"), ('#', "3"), ('$', "4"),
+	('%', "5"), ('^', "6"), ('&', "7"), ('*', "8"), ('(', "9"),
+	(')', "0"), ('_', "-"), ('+', "="), ('{', "["), ('}', "]"),
+	('|', "\\"), (':', ";"), ('"', "'"), ('<', ","), ('>', "."),
+	('?', "/"),
+	('1', "1"), ('2', "2"), ('3', "3"), ('4', "4"), ('5', "5"),
+	('6', "6"), ('7', "7"), ('8', "8"), ('9', "9"), ('0', "0")];
+
+pub fn render(layout: &Layout, dialect: Dialect, options: &ExportOptions)
+-> String
+{
+	let all: Vec<usize> = (0..34).collect();
+	let overrides = export::shift_overrides(layout, &all);
+
+	let mut s = String::new();
+	s.push_str(&format!(";; {} layout, generated by keygen.\n", options.name));
+	s.push_str(&export::diagram(layout, ";; "));
+	s.push('\n');
+
+	if dialect == Dialect::KMonad {
+		s.push_str(";; Set the input device for your system.\n");
+		s.push_str("(defcfg\n");
+		s.push_str("  input  (device-file \"/dev/input/by-id/usb-your-keyboard-event-kbd\")\n");
+		s.push_str(&format!("  output (uinput-sink \"keygen {}\")\n", options.name));
+		s.push_str("  fallthrough true\n");
+		s.push_str(")\n\n");
+	}
+
+	s.push_str("(defsrc\n");
+	s.push_str(&rows(&SOURCE_KEYS.iter().map(|k| k.to_string()).collect::<Vec<_>>()));
+	s.push_str(")\n\n");
+
+	let mut keys: Vec<String> = layout.lower().iter().map(|kc| key(*kc, dialect)).collect();
+	if !overrides.is_empty() {
+		if dialect == Dialect::Kanata {
+			// Shifted symbols that differ from a US keyboard.
+			s.push_str("(defalias\n");
+			for pos in overrides.iter() {
+				let upper = layout.upper()[*pos];
+				let shifted = if layout::is_shifted(upper) {
+					key(upper, dialect)
+				} else {
+					format!("(unshift {})", key(upper, dialect))
+				};
+				s.push_str(&format!("  shift{} (fork {} {} (lsft rsft))\n", pos, keys[*pos], shifted));
+			}
+			s.push_str(")\n\n");
+			for pos in overrides.iter() {
+				keys[*pos] = format!("@shift{}", pos);
+			}
+		} else {
+			s.push_str(";; KMonad can't change what shift types, so these keys type the\n");
+			s.push_str(";; US shifted symbol instead of the one on the upper layer:\n");
+			for pos in overrides.iter() {
+				s.push_str(&format!(";;   {} {}\n", layout::key_label(layout.lower()[*pos]), layout::key_label(layout.upper()[*pos])));
+			}
+			s.push('\n');
+		}
+	}
+
+	s.push_str("(deflayer base\n");
+	s.push_str(&rows(&keys));
+	s.push_str(")\n");
+
+	s
+}
+
+// The action that types `kc`.
+pub fn key(kc: char, dialect: Dialect)
+-> String
+{
+	if kc == '\0' {
+		return "XX".to_string();
+	}
+	if kc.is_ascii_lowercase() || kc.is_ascii_digit() {
+		return kc.to_string();
+	}
+	if kc.is_ascii_uppercase() {
+		return format!("S-{}", kc.to_ascii_lowercase());
+	}
+	if let Some(k) = KEYS.iter().find(|k| k.0 == kc) {
+		return if layout::is_shifted(kc) { format!("S-{}", k.1) } else { k.1.to_string() };
+	}
+	if let Some(codes) = layout::key_codes(kc) {
+		return match dialect {
+			Dialect::Kanata => codes.kanata.to_string(),
+			Dialect::KMonad => codes.kmonad.to_string(),
+		};
+	}
+
+	match dialect {
+		Dialect::Kanata => format!("(unicode {})", kc),
+		// KMonad can only type characters that it has compose sequences for.
+		Dialect::KMonad => kc.to_string(),
+	}
+}
+
+// Lays out one entry per `KeyMap` position in the rows of the geometry, with
+// a gap between the hands.
+fn rows(keys: &[String])
+-> String
+{
+	let width = keys.iter().map(|k| k.chars().count()).max().unwrap_or(0);
+	let cells = |keys: &[String]| -> String {
+		keys.iter().map(|k| format!("{:<w$}", k, w = width)).collect::<Vec<_>>().join(" ")
+	};
+
+	let mut s = String::new();
+	for &(start, split, end) in [(0, 5, 11), (11, 16, 22), (22, 27, 32)].iter() {
+		let line = format!("  {}   {}", cells(&keys[start..split]), cells(&keys[split..end]));
+		s.push_str(line.trim_end());
+		s.push('\n');
+	}
+	let thumbs = format!("  {}{}   {}", " ".repeat((width + 1) * 4), cells(&keys[32..33]), cells(&keys[33..34]));
+	s.push_str(thumbs.trim_end());
+	s.push('\n');
+	s
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	// Renders match the checked-in configurations of the reference layouts.
+	#[test]
+	fn renders_reference_layouts()
+	{
+		for &(name, layout, dialect, expected) in [
+			("qwerty", &layout::QWERTY_LAYOUT, Dialect::Kanata, include_str!("../testdata/export/qwerty.kbd")),
+			("dvorak", &layout::DVORAK_LAYOUT, Dialect::Kanata, include_str!("../testdata/export/dvorak.kbd")),
+			("init",   &layout::INIT_LAYOUT,   Dialect::Kanata, include_str!("../testdata/export/init.kbd")),
+			("qwerty", &layout::QWERTY_LAYOUT, Dialect::KMonad, include_str!("../testdata/export/qwerty.kmonad.kbd")),
+			("dvorak", &layout::DVORAK_LAYOUT, Dialect::KMonad, include_str!("../testdata/export/dvorak.kmonad.kbd")),
+			("init",   &layout::INIT_LAYOUT,   Dialect::KMonad, include_str!("../testdata/export/init.kmonad.kbd")),
+		].iter() {
+			assert_eq!(render(layout, dialect, &ExportOptions::new(name)), expected, "{}", name);
+		}
+	}
+}
@@ -0,0 +1,121 @@
+//! Renders a layout as a macOS `.keylayout` file.
+//!
+//! The layout goes on the key codes of the ANSI letter block, as for XKB. The
+//! Option key can't be remapped, so the first thumb key is left out. The
+//! other keys keep their US meaning, and Option and Control leave the layout
+//! as it is rather than typing their own symbols.
+
+use layout;
+use layout::Layout;
+use export::ExportOptions;
+use xml;
+
+// The key code of each `KeyMap` position; `None` for keys that can't be
+// remapped.
+static KEY_CODES: [Option<u8>; 34] = [
+	Some(12), Some(13), Some(14), Some(15), Some(17),   Some(16), Some(32), Some(34), Some(31), Some(35), Some(33),
+	Some(0),  Some(1),  Some(2),  Some(3),  Some(5),    Some(4),  Some(38), Some(40), Some(37), Some(41), Some(39),
+	Some(6),  Some(7),  Some(8),  Some(9),  Some(11),   Some(45), Some(46), Some(43), Some(47), Some(44),
+	None, Some(49)];
+
+// The keys outside the layout, as on a US keyboard: key code and the
+// characters of both levels.
+static US_KEYS: [(u8, char, char); 29] = [
+	(50, '`', '~'), (18, '1', '!'), (19, '2', '@'), (20, '3', '#'), (21, '4', '$'),
+	(23, '5', '%'), (22, '6', '^'), (26, '7', '&'), (28, '8', '*'), (25, '9', '('),
+	(29, '0', ')'), (27, '-', '_'), (24, '=', '+'), (30, ']', '}'), (42, '\\', '|'),
+	(36, '\r', '\r'), (48, '\t', '\t'), (51, '\x08', '\x08'), (53, '\x1b', '\x1b'),
+	(117, '\x7f', '\x7f'), (123, '\x1c', '\x1c'), (124, '\x1d', '\x1d'),
+	(125, '\x1f', '\x1f'), (126, '\x1e', '\x1e'), (115, '\x01', '\x01'),
+	(119, '\x04', '\x04'), (116, '\x0b', '\x0b'), (121, '\x0c', '\x0c'),
+	(76, '\x03', '\x03')];
+
+// What macOS types for the named keys that have an output.
+static NAMED_OUTPUTS: [(&str, char); 13] = [
+	("backspace", '\x08'), ("tab", '\t'), ("enter", '\r'), ("escape", '\x1b'),
+	("delete", '\x7f'), ("left", '\x1c'), ("right", '\x1d'), ("up", '\x1e'),
+	("down", '\x1f'), ("home", '\x01'), ("end", '\x04'), ("pageup", '\x0b'),
+	("pagedown", '\x0c')];
+
+pub fn render(layout: &Layout, options: &ExportOptions)
+-> String
+{
+	let name = xml::escape(&format!("{} (keygen)", options.name));
+
+	let mut s = String::new();
+	s.push_str("<?xml version=\"1.1\" encoding=\"UTF-8\"?>\n");
+	s.push_str("<!DOCTYPE keyboard SYSTEM \"file://localhost/System/Library/DTDs/KeyboardLayout.dtd\">\n");
+	s.push_str(&format!("<!-- {} layout, generated by keygen. -->\n", xml::escape(&options.name).replace("--", "- -")));
+	s.push_str(&format!("<keyboard group=\"126\" id=\"{}\" name=\"{}\" maxout=\"1\">\n", keyboard_id(&options.name), name));
+	s.push_str("    <layouts>\n");
+	s.push_str("        <layout first=\"0\" last=\"17\" modifiers=\"Modifiers\" mapSet=\"ANSI\"/>\n");
+	s.push_str("    </layouts>\n");
+	s.push_str("    <modifierMap id=\"Modifiers\" defaultIndex=\"0\">\n");
+	for (i, keys) in [
+		"command? anyOption? anyControl?",
+		"anyShift caps? command? anyOption? anyControl?",
+		"caps command? anyOption? anyControl?"].iter().enumerate() {
+		s.push_str(&format!("        <keyMapSelect mapIndex=\"{}\">\n", i));
+		s.push_str(&format!("            <modifier keys=\"{}\"/>\n", keys));
+		s.push_str("        </keyMapSelect>\n");
+	}
+	s.push_str("    </modifierMap>\n");
+	s.push_str("    <keyMapSet id=\"ANSI\">\n");
+
+	// Lower, upper, and caps lock: lower with letters capitalised.
+	for index in 0..3 {
+		s.push_str(&format!("        <keyMap index=\"{}\">\n", index));
+		let mut keys: Vec<(u8, char)> = Vec::new();
+		for &(code, lower, upper) in US_KEYS.iter() {
+			keys.push((code, if index == 1 { upper } else { lower }));
+		}
+		for (pos, code) in KEY_CODES.iter().enumerate() {
+			if let Some(code) = *code {
+				let lower = layout.lower()[pos];
+				let upper = layout.upper()[pos];
+				let kc = match index {
+					0 => lower,
+					1 => upper,
+					_ if lower.is_alphabetic() && layout::shifted(lower) == upper => upper,
+					_ => lower,
+				};
+				keys.push((code, kc));
+			}
+		}
+		keys.sort_by_key(|k| k.0);
+		for (code, kc) in keys {
+			s.push_str(&format!("            <key code=\"{}\" output=\"{}\"/>\n", code, xml::escape(&output(kc))));
+		}
+		s.push_str("        </keyMap>\n");
+	}
+
+	s.push_str("    </keyMapSet>\n");
+	s.push_str("</keyboard>\n");
+
+	s
+}
+
+// What a key on the layout types.
+fn output(kc: char)
+-> String
+{
+	if let Some(name) = layout::key_name(kc) {
+		if name != "space" {
+			return NAMED_OUTPUTS.iter().find(|k| k.0 == name).map(|k| k.1.to_string()).unwrap_or_default();
+		}
+	}
+	if kc == '\0' {
+		String::new()
+	} else {
+		kc.to_string()
+	}
+}
+
+// Custom layouts need a negative id that other installed layouts are unlikely
+// to use; derive one from the name so that reinstalling replaces the layout.
+fn keyboard_id(name: &str)
+-> i32
+{
+	let hash = name.bytes().fold(0u32, |h, b| h.wrapping_mul(31).wrapping_add(b as u32));
+	-((hash % 30000) as i32) - 1000
+}
@@ -0,0 +1,37 @@
+//! Reading keystroke logs: one key event per line, so that keys that don't
+//! type a character (Backspace, Enter, arrows, modifiers) are counted too.
+//!
+//! Each line holds a key, optionally preceded by other whitespace-separated
+//! fields such as a timestamp, which are ignored. A key is either a single
+//! character or the name of a key, e.g. `Backspace`, `Enter` or `Space`.
+
+use std::io::BufRead;
+
+use error::Error;
+use error::Result;
+use layout;
+use penalty::QuartadCounter;
+
+pub fn read_keylog<R: BufRead>(reader: R, name: &str, counter: &mut QuartadCounter)
+-> Result<()>
+{
+	for (i, line) in reader.lines().enumerate() {
+		let line = line.map_err(|e| Error::corpus_line(e, name, i + 1))?;
+		let key = match line.split_whitespace().last() {
+			Some(key) => key,
+			None => continue,
+		};
+
+		let mut chars = key.chars();
+		let kc = match (chars.next(), chars.next()) {
+			(Some(c), None) => Some(c),
+			_ => layout::named_key(key),
+		};
+		match kc {
+			Some(kc) => counter.push_key(kc),
+			None => return Err(Error::corpus(name, i + 1, format!("unknown key {:?}", key))),
+		}
+	}
+
+	Ok(())
+}
@@ -0,0 +1,149 @@
+//! Renders a layout as a Microsoft Keyboard Layout Creator `.klc` source file.
+//!
+//! The layout goes on the scan codes of the US letter block, as for XKB. The
+//! left Alt key can't be remapped, so the first thumb key is left out, and
+//! keys that don't type a character, such as Backspace, can't be moved. All
+//! other keys keep their US meaning. MSKLC only reads UTF-16 files; see
+//! `encode`.
+
+use layout;
+use layout::Layout;
+use export;
+use export::ExportOptions;
+
+// The scan code of each `KeyMap` position, and the virtual key it has on a
+// US keyboard; `None` for keys that can't be remapped.
+static SCAN_CODES: [Option<(&str, &str)>; 34] = [
+	Some(("10", "Q")), Some(("11", "W")), Some(("12", "E")), Some(("13", "R")), Some(("14", "T")),
+	Some(("15", "Y")), Some(("16", "U")), Some(("17", "I")), Some(("18", "O")), Some(("19", "P")),
+	Some(("1a", "OEM_4")),
+	Some(("1e", "A")), Some(("1f", "S")), Some(("20", "D")), Some(("21", "F")), Some(("22", "G")),
+	Some(("23", "H")), Some(("24", "J")), Some(("25", "K")), Some(("26", "L")), Some(("27", "OEM_1")),
+	Some(("28", "OEM_7")),
+	Some(("2c", "Z")), Some(("2d", "X")), Some(("2e", "C")), Some(("2f", "V")), Some(("30", "B")),
+	Some(("31", "N")), Some(("32", "M")), Some(("33", "OEM_COMMA")), Some(("34", "OEM_PERIOD")),
+	Some(("35", "OEM_2")),
+	None, Some(("39", "SPACE"))];
+
+// The keys outside the layout, as on a US keyboard: scan code, virtual key,
+// and the characters of both levels.
+static US_KEYS: [(&str, &str, char, char); 15] = [
+	("29", "OEM_3", '`', '~'), ("02", "1", '1', '!'), ("03", "2", '2', '@'),
+	("04", "3", '3', '#'), ("05", "4", '4', '$'), ("06", "5", '5', '%'),
+	("07", "6", '6', '^'), ("08", "7", '7', '&'), ("09", "8", '8', '*'),
+	("0a", "9", '9', '('), ("0b", "0", '0', ')'), ("0c", "OEM_MINUS", '-', '_'),
+	("0d", "OEM_PLUS", '=', '+'), ("1b", "OEM_6", ']', '}'), ("2b", "OEM_5", '\\', '|')];
+
+pub fn render(layout: &Layout, options: &ExportOptions)
+-> String
+{
+	let id = export::identifier(&options.name);
+	// Keyboard names are limited to eight characters.
+	let kbd: String = format!("kg{}", id.replace('_', "")).chars().take(8).collect();
+	let description = format!("{} (keygen)", options.name.replace('"', "'"));
+
+	let mut s = String::new();
+	s.push_str(&format!("KBD\t{}\t\"{}\"\r\n\r\n", kbd, description));
+	s.push_str("COMPANY\t\"keygen\"\r\n\r\n");
+	s.push_str("LOCALENAME\t\"en-US\"\r\n\r\n");
+	s.push_str("LOCALEID\t\"00000409\"\r\n\r\n");
+	s.push_str("VERSION\t1.0\r\n\r\n");
+	s.push_str("SHIFTSTATE\r\n\r\n");
+	s.push_str("0\t//Column 4\r\n");
+	s.push_str("1\t//Column 5 : Shft\r\n");
+	s.push_str("2\t//Column 6 :       Ctrl\r\n\r\n");
+	s.push_str("LAYOUT\t\t;an extra '@' at the end is a dead key\r\n\r\n");
+	s.push_str("//SC\tVK_\t\tCap\t0\t1\t2\r\n");
+	s.push_str("//--\t----\t\t----\t----\t----\t----\r\n\r\n");
+
+	for &(sc, vk, lower, upper) in US_KEYS.iter() {
+		s.push_str(&row(sc, vk, lower, upper));
+	}
+	let vks = virtual_keys(layout);
+	for pos in 0..34 {
+		if let Some((sc, _)) = SCAN_CODES[pos] {
+			s.push_str(&row(sc, vks[pos], layout.lower()[pos], layout.upper()[pos]));
+		}
+	}
+	s.push_str(&row("53", "DECIMAL", '.', '.'));
+
+	s.push_str("\r\n\r\nDESCRIPTIONS\r\n\r\n");
+	s.push_str(&format!("0409\t{}\r\n\r\n", description));
+	s.push_str("LANGUAGENAMES\r\n\r\n");
+	s.push_str("0409\tEnglish (United States)\r\n\r\n");
+	s.push_str("ENDKBD\r\n");
+
+	s
+}
+
+// The file as MSKLC expects it: UTF-16LE with a byte order mark.
+pub fn encode(s: &str)
+-> Vec<u8>
+{
+	let mut bytes = vec![0xff, 0xfe];
+	for unit in s.encode_utf16() {
+		bytes.extend_from_slice(&unit.to_le_bytes());
+	}
+	bytes
+}
+
+fn row(sc: &str, vk: &str, lower: char, upper: char)
+-> String
+{
+	// Letters that shift changes to their capital also follow caps lock.
+	let cap = if lower.is_alphabetic() && layout::shifted(lower) == upper { 1 } else { 0 };
+	let pad = if vk.len() < 8 { "\t\t" } else { "\t" };
+	format!("{}\t{}{}{}\t{}\t{}\t-1\r\n", sc, vk, pad, cap, character(lower), character(upper))
+}
+
+fn character(kc: char)
+-> String
+{
+	if kc == '\0' || layout::key_name(kc).map(|n| n != "space").unwrap_or(false) {
+		"-1".to_string()
+	} else if kc.is_ascii_alphanumeric() {
+		kc.to_string()
+	} else {
+		format!("{:04x}", kc as u32)
+	}
+}
+
+// Keys that type a letter get that letter's virtual key, so that shortcuts
+// follow the layout. The other keys get the remaining virtual keys of the
+// letter block, preferring their own.
+fn virtual_keys(layout: &Layout)
+-> Vec<&'static str>
+{
+	let mut vks: Vec<Option<&'static str>> = vec![None; 34];
+	let mut used: Vec<&'static str> = Vec::new();
+	for pos in 0..34 {
+		let kc = layout.lower()[pos];
+		if SCAN_CODES[pos].is_none() || !kc.is_ascii_alphabetic() {
+			continue;
+		}
+		let letter = kc.to_ascii_uppercase();
+		let vk = SCAN_CODES.iter().filter_map(|k| k.map(|k| k.1)).find(|vk| vk.chars().eq(Some(letter)));
+		if let Some(vk) = vk {
+			if !used.contains(&vk) {
+				vks[pos] = Some(vk);
+				used.push(vk);
+			}
+		}
+	}
+
+	let mut free: Vec<&'static str> = SCAN_CODES.iter()
+		.filter_map(|k| k.map(|k| k.1))
+		.filter(|vk| !used.contains(vk))
+		.collect();
+	for pos in 0..34 {
+		if vks[pos].is_some() {
+			continue;
+		}
+		if let Some((_, own)) = SCAN_CODES[pos] {
+			let i = free.iter().position(|vk| *vk == own).unwrap_or(0);
+			vks[pos] = Some(free.remove(i));
+		}
+	}
+
+	vks.into_iter().map(|vk| vk.unwrap_or("")).collect()
+}
@@ -0,0 +1,375 @@
+//! Reading layouts from keyboard-layout-editor.com JSON, either the raw data
+//! or the downloaded file.
+//!
+//! Keys are placed by their position on the drawn keyboard: the last three
+//! rows with at least ten character keys are the letter rows, from which the
+//! first 11, 11 and 10 keys are taken, and the two character or space keys
+//! nearest the middle below them are the thumb keys. A key's bottom legend is
+//! its lower key and its top legend the upper one; a key with only a letter
+//! types that letter in both cases. Rotation is ignored.
+
+use error::LayoutParseError;
+use layout;
+use import::ImportedKeys;
+
+static MODIFIERS: [&str; 6] = ["shift", "control", "ctrl", "alt", "super", "capslock"];
+
+enum Value
+{
+	Str(String),
+	Num(f64),
+	Arr(Vec<Value>),
+	Obj(Vec<(String, Value)>),
+	Other,
+}
+
+struct Key
+{
+	x:      f64,
+	y:      f64,
+	w:      f64,
+	legend: String,
+}
+
+// Whether `s` looks like keyboard-layout-editor data.
+pub fn is_kle(s: &str)
+-> bool
+{
+	let s = s.trim_start();
+	if s.starts_with('{') {
+		return true;
+	}
+	s.starts_with('[') && matches!(s[1..].trim_start().chars().next(), Some('[') | Some('{') | Some('"'))
+}
+
+pub fn parse(s: &str)
+-> Result<ImportedKeys, LayoutParseError>
+{
+	// The raw data is a comma-separated list of rows rather than an array.
+	let mut p = Parser { s: s.as_bytes(), i: 0 };
+	let mut items = Vec::new();
+	loop {
+		p.skip_space();
+		if p.i == p.s.len() {
+			break;
+		}
+		items.push(p.value()?);
+		p.skip_space();
+		p.eat(b',');
+	}
+	if items.len() == 1 {
+		if let Value::Arr(ref inner) = items[0] {
+			if inner.iter().all(|v| matches!(*v, Value::Arr(_) | Value::Obj(_))) {
+				items = match items.pop() {
+					Some(Value::Arr(inner)) => inner,
+					_ => unreachable!(),
+				};
+			}
+		}
+	}
+
+	let rows = visual_rows(positions(&items));
+	let letter_rows: Vec<usize> = (0..rows.len())
+		.filter(|&r| rows[r].iter().filter(|k| character(k).is_some()).count() >= 10)
+		.collect();
+	if letter_rows.len() < 3 {
+		return Err(LayoutParseError::new("expected at least three rows of ten or more character keys"));
+	}
+	let letter_rows = &letter_rows[(letter_rows.len() - 3)..];
+
+	let mut keys: ImportedKeys = [None; 34];
+	for (row, &r) in letter_rows.iter().enumerate() {
+		let start = [0, 11, 22][row];
+		let len = [11, 11, 10][row];
+		for (i, key) in rows[r].iter().filter_map(character).take(len).enumerate() {
+			keys[start + i] = Some(key);
+		}
+	}
+
+	// Thumb keys: the two nearest the middle of the home row, left to right.
+	let home = &rows[letter_rows[1]];
+	let middle = home.iter().map(|k| k.x + k.w / 2.0).sum::<f64>() / home.len() as f64;
+	let mut thumbs: Vec<(&Key, (char, char))> = rows[(letter_rows[2] + 1)..].iter()
+		.flat_map(|row| row.iter())
+		.filter_map(|k| thumb(k).map(|t| (k, t)))
+		.collect();
+	thumbs.sort_by(|a, b| distance(a.0, middle).total_cmp(&distance(b.0, middle)));
+	thumbs.truncate(2);
+	thumbs.sort_by(|a, b| a.0.x.total_cmp(&b.0.x));
+	let first = 34 - thumbs.len();
+	for (i, &(_, key)) in thumbs.iter().enumerate() {
+		keys[first + i] = Some(key);
+	}
+
+	Ok(keys)
+}
+
+// Works out where each key is drawn, following the editor's rules: each row
+// starts below the previous one, and a key starts where the previous one
+// ended, unless properties before it move it.
+fn positions(rows: &[Value])
+-> Vec<Key>
+{
+	let mut keys = Vec::new();
+	let (mut x, mut y) = (0.0, 0.0);
+	let (mut rx, mut ry) = (0.0, 0.0);
+	let mut w = 1.0;
+	for row in rows {
+		let items = match *row {
+			Value::Arr(ref items) => items,
+			_ => continue,
+		};
+		for item in items {
+			match *item {
+				Value::Obj(ref props) => {
+					for (name, value) in props {
+						let n = match *value { Value::Num(n) => n, _ => continue };
+						match &name[..] {
+							"rx" => { rx = n; x = rx; y = ry; },
+							"ry" => { ry = n; x = rx; y = ry; },
+							"x" => x += n,
+							"y" => y += n,
+							"w" => w = n,
+							_ => (),
+						}
+					}
+				},
+				Value::Str(ref legend) => {
+					keys.push(Key { x, y, w, legend: legend.clone() });
+					x += w;
+					w = 1.0;
+				},
+				_ => (),
+			}
+		}
+		y += 1.0;
+		x = rx;
+	}
+	keys
+}
+
+// Groups keys into rows by their vertical position, top to bottom, with each
+// row sorted left to right. Keys less than half a key apart are on one row,
+// so that column stagger doesn't split rows.
+fn visual_rows(mut keys: Vec<Key>)
+-> Vec<Vec<Key>>
+{
+	keys.sort_by(|a, b| a.y.partial_cmp(&b.y).unwrap());
+	let mut rows: Vec<Vec<Key>> = Vec::new();
+	for key in keys {
+		let new_row = match rows.last() {
+			Some(row) => key.y - row[0].y >= 0.5,
+			None => true,
+		};
+		if new_row {
+			rows.push(Vec::new());
+		}
+		rows.last_mut().unwrap().push(key);
+	}
+	for row in rows.iter_mut() {
+		row.sort_by(|a, b| a.x.partial_cmp(&b.x).unwrap());
+	}
+	rows
+}
+
+fn distance(key: &Key, middle: f64)
+-> f64
+{
+	(key.x + key.w / 2.0 - middle).abs()
+}
+
+// The keys typed by a key whose legends are single characters, other than
+// the space bar.
+fn character(key: &Key)
+-> Option<(char, char)>
+{
+	let legends: Vec<&str> = key.legend.split('\n').collect();
+	let single = |s: &str| -> Option<char> {
+		let mut chars = s.trim().chars();
+		match (chars.next(), chars.next()) {
+			(Some(c), None) => Some(layout::key_from_label(c)),
+			_ => None,
+		}
+	};
+
+	let top = single(legends[0])?;
+	if top == ' ' {
+		return None;
+	}
+	match legends.get(1).and_then(|l| single(l)) {
+		Some(bottom) => Some((bottom, top)),
+		None if top.is_alphabetic() => {
+			let lower: String = top.to_lowercase().collect();
+			let lower = lower.chars().next().unwrap_or(top);
+			Some((lower, layout::shifted(lower)))
+		},
+		None => Some((top, layout::shifted(top))),
+	}
+}
+
+// A character key, the space bar (a wide blank key), or a key labelled with
+// the name of a key other than a modifier, such as Enter.
+fn thumb(key: &Key)
+-> Option<(char, char)>
+{
+	let legend = key.legend.trim();
+	if legend.is_empty() && key.w >= 2.0 {
+		return Some((' ', ' '));
+	}
+	let name = legend.to_lowercase().replace(' ', "");
+	match layout::named_key(&name) {
+		Some(_) if MODIFIERS.contains(&&name[..]) => None,
+		Some(kc) => Some((kc, kc)),
+		None => character(key),
+	}
+}
+
+// Just enough of a JSON parser for the editor's data, which also allows
+// unquoted property names.
+struct Parser<'a>
+{
+	s: &'a [u8],
+	i: usize,
+}
+
+impl<'a> Parser<'a>
+{
+	fn value(&mut self)
+	-> Result<Value, LayoutParseError>
+	{
+		self.skip_space();
+		match self.s.get(self.i) {
+			Some(b'[') => {
+				self.i += 1;
+				let mut items = Vec::new();
+				loop {
+					self.skip_space();
+					if self.eat(b']') {
+						return Ok(Value::Arr(items));
+					}
+					items.push(self.value()?);
+					self.skip_space();
+					if !self.eat(b',') && self.s.get(self.i) != Some(&b']') {
+						return Err(self.error("expected , or ]"));
+					}
+				}
+			},
+			Some(b'{') => {
+				self.i += 1;
+				let mut props = Vec::new();
+				loop {
+					self.skip_space();
+					if self.eat(b'}') {
+						return Ok(Value::Obj(props));
+					}
+					let name = match self.s.get(self.i) {
+						Some(b'"') => self.string()?,
+						_ => self.word(),
+					};
+					self.skip_space();
+					if !self.eat(b':') {
+						return Err(self.error("expected :"));
+					}
+					let value = self.value()?;
+					props.push((name, value));
+					self.skip_space();
+					if !self.eat(b',') && self.s.get(self.i) != Some(&b'}') {
+						return Err(self.error("expected , or }"));
+					}
+				}
+			},
+			Some(b'"') => Ok(Value::Str(self.string()?)),
+			Some(_) => {
+				let word = self.word();
+				if word.is_empty() {
+					return Err(self.error("unexpected character"));
+				}
+				// JSON has no NaN or infinity, which would leave keys nowhere.
+				match word.parse::<f64>() {
+					Ok(n) if n.is_finite() => Ok(Value::Num(n)),
+					Ok(_) => Err(self.error("invalid number")),
+					Err(_) => Ok(Value::Other),
+				}
+			},
+			None => Err(self.error("unexpected end of data")),
+		}
+	}
+
+	fn string(&mut self)
+	-> Result<String, LayoutParseError>
+	{
+		self.i += 1;
+		let mut bytes = Vec::new();
+		loop {
+			match self.s.get(self.i) {
+				None => return Err(self.error("unterminated string")),
+				Some(b'"') => {
+					self.i += 1;
+					return String::from_utf8(bytes).map_err(|_| self.error("invalid UTF-8"));
+				},
+				Some(b'\\') => {
+					let escaped = match self.s.get(self.i + 1) {
+						Some(b'n') => '\n',
+						Some(b't') => '\t',
+						Some(b'r') => '\r',
+						Some(b'b') => '\x08',
+						Some(b'f') => '\x0c',
+						Some(b'u') => {
+							let hex = self.s.get((self.i + 2)..(self.i + 6))
+								.and_then(|h| std::str::from_utf8(h).ok())
+								.and_then(|h| u32::from_str_radix(h, 16).ok())
+								.ok_or_else(|| self.error("invalid \\u escape"))?;
+							self.i += 4;
+							std::char::from_u32(hex).unwrap_or('\u{fffd}')
+						},
+						Some(&c) => c as char,
+						None => return Err(self.error("unterminated string")),
+					};
+					let mut buf = [0u8; 4];
+					bytes.extend_from_slice(escaped.encode_utf8(&mut buf).as_bytes());
+					self.i += 2;
+				},
+				Some(&b) => {
+					bytes.push(b);
+					self.i += 1;
+				},
+			}
+		}
+	}
+
+	// A number, a literal such as `true`, or an unquoted property name.
+	fn word(&mut self)
+	-> String
+	{
+		let start = self.i;
+		while self.s.get(self.i).map(|b| b.is_ascii_alphanumeric() || b"+-._".contains(b)).unwrap_or(false) {
+			self.i += 1;
+		}
+		String::from_utf8_lossy(&self.s[start..self.i]).into_owned()
+	}
+
+	fn skip_space(&mut self)
+	{
+		while self.s.get(self.i).map(|b| b.is_ascii_whitespace()).unwrap_or(false) {
+			self.i += 1;
+		}
+	}
+
+	fn eat(&mut self, b: u8)
+	-> bool
+	{
+		if self.s.get(self.i) == Some(&b) {
+			self.i += 1;
+			true
+		} else {
+			false
+		}
+	}
+
+	fn error(&self, message: &str)
+	-> LayoutParseError
+	{
+		let line = self.s[..self.i.min(self.s.len())].iter().filter(|&&b| b == b'\n').count() + 1;
+		LayoutParseError::at_line(line, message)
+	}
+}
@@ -1,9 +1,9 @@
-/// Data structures and methods for creating and shuffling keyboard layouts.
+//! Data structures and methods for creating and shuffling keyboard layouts.
 
 extern crate rand;
 
 use std::fmt;
-use self::rand::random;
+use self::rand::Rng;
 
 /* ----- *
  * TYPES *
@@ -17,6 +17,7 @@ use self::rand::random;
 //
 //             32 | 33 (thumb keys)
 
+#[derive(PartialEq, Eq, Hash)]
 pub struct KeyMap<T>(pub [T; 34]);
 
 impl <T: Copy> Clone for KeyMap<T>
@@ -28,17 +29,21 @@ impl <T: Copy> Clone for KeyMap<T>
 	}
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub struct Layer(KeyMap<char>);
 
-#[derive(Clone)]
+// Layouts are equal when every position has the same keys on both layers.
+// Empty positions are all '\0', so layouts that differ only by which empty
+// positions were swapped are equal and hash alike.
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub struct Layout(Layer, Layer);
 
-pub struct LayoutPermutations
+// Every layout within some number of swaps of a layout, each once.
+pub struct SwapNeighbourhood<'a>
 {
-	orig_layout: Layout,
-	swap_idx: Vec<usize>,
-	started: bool,
+	layout:    &'a Layout,
+	positions: Vec<usize>,
+	max_swaps: usize,
 }
 
 pub struct LayoutPosMap([Option<KeyPress>; 128]);
@@ -187,17 +192,12 @@ pub static ARENSITO_LAYOUT: Layout = Layout(
 	              'Z', 'W', '>', 'H', 'J',   'V', 'C', 'Y', 'M', 'X',
 	              '\0', ' '])));
 
-// static LAYOUT_MASK: LayoutShuffleMask = LayoutShuffleMask(KeyMap([
-// 	true,  true,  true,  true,  true,  true,  true,  true,  true,  true,  false,
-// 	true,  true,  true,  true,  true,  true,  true,  true,  true,  true,  true,
-// 	true,  true,  true,  true,  true,  true,  true,  true,  true,  true,
-// 	false]));
-static LAYOUT_MASK_SWAP_OFFSETS: [usize; 33] = [
-	0, 0, 0, 0, 0,    0, 0, 0, 0, 0,
-	1, 1, 1, 1, 1,    1, 1, 1, 1, 1, 1,
-	1, 1, 1, 1, 1,    1, 1, 1, 1, 1,
-	1, 1];
-static LAYOUT_MASK_NUM_SWAPPABLE: usize = 33;
+// Positions that shuffling may move when the layout pins no keys of its own.
+pub static LAYOUT_MASK: LayoutShuffleMask = LayoutShuffleMask(KeyMap([
+	true,  true,  true,  true,  true,  true,  true,  true,  true,  true,  false,
+	true,  true,  true,  true,  true,  true,  true,  true,  true,  true,  true,
+	true,  true,  true,  true,  true,  true,  true,  true,  true,  true,
+	true,  true]));
 
 static KEY_FINGERS: KeyMap<Finger> = KeyMap([
 	Finger::Pinky, Finger::Ring, Finger::Middle, Finger::Index, Finger::Index,    Finger::Index, Finger::Index, Finger::Middle, Finger::Ring, Finger::Pinky, Finger::Pinky,
@@ -222,15 +222,183 @@ static KEY_CENTER_COLUMN: KeyMap<bool> = KeyMap([
 
 pub static KP_NONE: Option<KeyPress> = None;
 
+// Keys that don't type a character are represented by ASCII control
+// characters, so that they can be placed in a `Layer` and looked up in a
+// `LayoutPosMap` like any other key. Each has a glyph that is used to display
+// it and to place it in a layout file. Names are matched case-insensitively;
+// where several names share a key, the first is canonical.
+static NAMED_KEYS: [(&str, char, char); 24] = [
+	("space",     ' ',    ' '),
+	("backspace", '\x08', '⌫'),
+	("tab",       '\t',   '⇥'),
+	("enter",     '\n',   '⏎'),
+	("return",    '\n',   '⏎'),
+	("escape",    '\x1b', '⎋'),
+	("esc",       '\x1b', '⎋'),
+	("delete",    '\x7f', '⌦'),
+	("del",       '\x7f', '⌦'),
+	("left",      '\x11', '←'),
+	("right",     '\x12', '→'),
+	("up",        '\x13', '↑'),
+	("down",      '\x14', '↓'),
+	("home",      '\x01', '⇱'),
+	("end",       '\x04', '⇲'),
+	("pageup",    '\x16', '⇞'),
+	("pagedown",  '\x17', '⇟'),
+	("shift",     '\x0e', '⇧'),
+	("control",   '\x0f', '⌃'),
+	("ctrl",      '\x0f', '⌃'),
+	("alt",       '\x10', '⌥'),
+	("super",     '\x15', '◆'),
+	("capslock",  '\x18', '⇪'),
+	("compose",   '\x19', '⎄'),
+];
+
+// What the export formats call each named key other than space.
+pub struct KeyCodes
+{
+	pub qmk:    &'static str,
+	pub zmk:    &'static str,
+	pub kanata: &'static str,
+	pub kmonad: &'static str,
+	pub xkb:    &'static str,
+}
+
+static NAMED_KEY_CODES: [(char, KeyCodes); 19] = [
+	('\x08', KeyCodes { qmk: "KC_BSPC", zmk: "BSPC",  kanata: "bspc", kmonad: "bspc", xkb: "BackSpace" }),
+	('\t',   KeyCodes { qmk: "KC_TAB",  zmk: "TAB",   kanata: "tab",  kmonad: "tab",  xkb: "Tab" }),
+	('\n',   KeyCodes { qmk: "KC_ENT",  zmk: "RET",   kanata: "ret",  kmonad: "ret",  xkb: "Return" }),
+	('\x1b', KeyCodes { qmk: "KC_ESC",  zmk: "ESC",   kanata: "esc",  kmonad: "esc",  xkb: "Escape" }),
+	('\x7f', KeyCodes { qmk: "KC_DEL",  zmk: "DEL",   kanata: "del",  kmonad: "del",  xkb: "Delete" }),
+	('\x11', KeyCodes { qmk: "KC_LEFT", zmk: "LEFT",  kanata: "left", kmonad: "left", xkb: "Left" }),
+	('\x12', KeyCodes { qmk: "KC_RGHT", zmk: "RIGHT", kanata: "rght", kmonad: "rght", xkb: "Right" }),
+	('\x13', KeyCodes { qmk: "KC_UP",   zmk: "UP",    kanata: "up",   kmonad: "up",   xkb: "Up" }),
+	('\x14', KeyCodes { qmk: "KC_DOWN", zmk: "DOWN",  kanata: "down", kmonad: "down", xkb: "Down" }),
+	('\x01', KeyCodes { qmk: "KC_HOME", zmk: "HOME",  kanata: "home", kmonad: "home", xkb: "Home" }),
+	('\x04', KeyCodes { qmk: "KC_END",  zmk: "END",   kanata: "end",  kmonad: "end",  xkb: "End" }),
+	('\x16', KeyCodes { qmk: "KC_PGUP", zmk: "PG_UP", kanata: "pgup", kmonad: "pgup", xkb: "Prior" }),
+	('\x17', KeyCodes { qmk: "KC_PGDN", zmk: "PG_DN", kanata: "pgdn", kmonad: "pgdn", xkb: "Next" }),
+	('\x0e', KeyCodes { qmk: "KC_LSFT", zmk: "LSHFT", kanata: "lsft", kmonad: "lsft", xkb: "Shift_L" }),
+	('\x0f', KeyCodes { qmk: "KC_LCTL", zmk: "LCTRL", kanata: "lctl", kmonad: "lctl", xkb: "Control_L" }),
+	('\x10', KeyCodes { qmk: "KC_LALT", zmk: "LALT",  kanata: "lalt", kmonad: "lalt", xkb: "Alt_L" }),
+	('\x15', KeyCodes { qmk: "KC_LGUI", zmk: "LGUI",  kanata: "lmet", kmonad: "lmet", xkb: "Super_L" }),
+	('\x18', KeyCodes { qmk: "KC_CAPS", zmk: "CAPS",  kanata: "caps", kmonad: "caps", xkb: "Caps_Lock" }),
+	('\x19', KeyCodes { qmk: "KC_APP",  zmk: "K_APP", kanata: "comp", kmonad: "cmp",  xkb: "Multi_key" }),
+];
+
 static LAYOUT_FILE_IDXS: KeyMap<usize> = KeyMap([
 	0,  1,  2,  3,  4,     6,  7,  8,  9,  10, 11,
 	13, 14, 15, 16, 17,    19, 20, 21, 22, 23, 24,
 	26, 27, 28, 29, 30,    32, 33, 34, 35, 36, 37, 38]);
 
+static SHIFT_PAIRS: [(char, char); 21] = [
+	('`', '~'), ('1', '!'), ('2', '@'), ('3', '#'), ('4', '$'), ('5', '%'),
+	('6', '^'), ('7', '&'), ('8', '*'), ('9', '('), ('0', ')'), ('-', '_'),
+	('=', '+'), ('[', '{'), (']', '}'), ('\\', '|'), (';', ':'), ('\'', '"'),
+	(',', '<'), ('.', '>'), ('/', '?')];
+
 /* ----- *
  * IMPLS *
  * ----- */
 
+// Looks up a named key, e.g. `Backspace` or `Enter`.
+pub fn named_key(name: &str)
+-> Option<char>
+{
+	let name = name.to_lowercase();
+	NAMED_KEYS.iter().find(|k| k.0 == name).map(|k| k.1)
+}
+
+// The canonical name of a named key.
+pub fn key_name(kc: char)
+-> Option<&'static str>
+{
+	NAMED_KEYS.iter().find(|k| k.1 == kc).map(|k| k.0)
+}
+
+// What the export formats call the named key `kc`.
+pub fn key_codes(kc: char)
+-> Option<&'static KeyCodes>
+{
+	NAMED_KEY_CODES.iter().find(|k| k.0 == kc).map(|k| &k.1)
+}
+
+// The named key whose codes `f` picks out.
+pub fn key_from_codes<F>(f: F)
+-> Option<char>
+where F: Fn(&KeyCodes) -> bool
+{
+	NAMED_KEY_CODES.iter().find(|k| f(&k.1)).map(|k| k.0)
+}
+
+// The character that shift produces for `kc` on a US keyboard.
+pub fn shifted(kc: char)
+-> char
+{
+	if let Some(pair) = SHIFT_PAIRS.iter().find(|p| p.0 == kc) {
+		return pair.1;
+	}
+	let mut upper = kc.to_uppercase();
+	match (upper.next(), upper.next()) {
+		(Some(c), None) => c,
+		_ => kc,
+	}
+}
+
+// Whether `kc` is typed with shift on a US keyboard.
+pub fn is_shifted(kc: char)
+-> bool
+{
+	kc.is_uppercase() || SHIFT_PAIRS.iter().any(|p| p.1 == kc)
+}
+
+// The hand and finger that press the key at `pos`.
+pub fn finger_of(pos: usize)
+-> (Hand, Finger)
+{
+	(KEY_HANDS.0[pos], KEY_FINGERS.0[pos])
+}
+
+// Pressing `kc` at `pos`.
+pub fn key_press(kc: char, pos: usize)
+-> KeyPress
+{
+	KeyPress {
+		kc,
+		pos,
+		finger: KEY_FINGERS.0[pos],
+		hand:   KEY_HANDS.0[pos],
+		row:    KEY_ROWS.0[pos],
+		center: KEY_CENTER_COLUMN.0[pos],
+	}
+}
+
+// The glyph used to display a key; characters stand for themselves.
+pub fn key_label(kc: char)
+-> char
+{
+	match NAMED_KEYS.iter().find(|k| k.1 == kc) {
+		Some(k) => k.2,
+		None => kc,
+	}
+}
+
+pub fn key_labels(s: &str)
+-> String
+{
+	s.chars().map(key_label).collect()
+}
+
+// The key a character in a layout file stands for.
+pub fn key_from_label(label: char)
+-> char
+{
+	match NAMED_KEYS.iter().find(|k| k.2 == label) {
+		Some(k) => k.1,
+		None => label,
+	}
+}
+
 impl Layout
 {
 	pub fn from_string(s: &str)
@@ -242,23 +410,123 @@ impl Layout
 		
 		for i in 0..34 {
 			let file_i = LAYOUT_FILE_IDXS.0[i];
-			lower[i] = *s.get(file_i).unwrap_or(&'\0');
-			upper[i] = *s.get(file_i + 40).unwrap_or(&'\0');
+			lower[i] = key_from_label(*s.get(file_i).unwrap_or(&'\0'));
+			upper[i] = key_from_label(*s.get(file_i + 40).unwrap_or(&'\0'));
 		}
 
 		Layout(Layer(KeyMap(lower)), Layer(KeyMap(upper)))
 	}
 
-	pub fn shuffle(&mut self, times: usize)
+	pub fn new(lower: [char; 34], upper: [char; 34])
+	-> Layout
+	{
+		Layout(Layer(KeyMap(lower)), Layer(KeyMap(upper)))
+	}
+
+	pub fn lower(&self)
+	-> &[char; 34]
 	{
+		&((self.0).0).0
+	}
+
+	pub fn upper(&self)
+	-> &[char; 34]
+	{
+		&((self.1).0).0
+	}
+
+	pub fn shuffle<R: Rng>(&mut self, times: usize, mask: &LayoutShuffleMask, rng: &mut R)
+	{
+		let positions = mask.positions();
+		if positions.len() < 2 {
+			return;
+		}
 		for _ in 0..times {
-			let (i, j) = Layout::shuffle_position();
+			let (i, j) = Layout::shuffle_position(&positions, rng);
 			let Layout(ref mut lower, ref mut upper) = *self;
 			lower.swap(i, j);
 			upper.swap(i, j);
 		}
 	}
 
+	// The layout with its keys moved round `cycles`: in each cycle, the key at
+	// each position goes to the next, and the key at the last to the first.
+	pub fn rearranged<C>(&self, cycles: &[C])
+	-> Layout
+	where C: AsRef<[usize]>
+	{
+		let mut layout = self.clone();
+		for cycle in cycles {
+			for pair in cycle.as_ref().windows(2).rev() {
+				let Layout(ref mut lower, ref mut upper) = layout;
+				lower.swap(pair[0], pair[1]);
+				upper.swap(pair[0], pair[1]);
+			}
+		}
+		layout
+	}
+
+	// The fewest swaps that turn this layout into `other`, or None if the two
+	// don't have the same keys.
+	pub fn swap_distance(&self, other: &Layout)
+	-> Option<usize>
+	{
+		let key = |l: &Layout, pos: usize| (l.lower()[pos], l.upper()[pos]);
+		let moved: Vec<usize> = (0..34).filter(|&pos| key(self, pos) != key(other, pos)).collect();
+
+		// For each moved position, where its key in `other` is in this layout.
+		// Following these from position to position goes round cycles, and a
+		// cycle of n positions takes n - 1 swaps.
+		let mut source = [None; 34];
+		let mut receiver = [None; 34];
+		for &pos in moved.iter() {
+			let mut from = moved.iter().cloned().filter(|&from| key(self, from) == key(other, pos));
+			match (from.next(), from.next()) {
+				(None, _) => return None,
+				(Some(from), None) => {
+					source[pos] = Some(from);
+					receiver[from] = Some(pos);
+				},
+				_ => (),
+			}
+		}
+
+		// A key that several positions hold, such as an empty one, may come
+		// from any of them. Where the keys that moved on from a position end at
+		// one of those, taking it from there closes the shortest cycle.
+		for &pos in moved.iter() {
+			if source[pos].is_some() {
+				continue;
+			}
+			let mut end = pos;
+			while let Some(next) = receiver[end] {
+				end = next;
+			}
+			let from = if key(self, end) == key(other, pos) {
+				end
+			} else {
+				moved.iter().cloned().find(|&from| receiver[from].is_none() && key(self, from) == key(other, pos))?
+			};
+			source[pos] = Some(from);
+			receiver[from] = Some(pos);
+		}
+
+		let mut visited = [false; 34];
+		let mut cycles = 0;
+		for &start in moved.iter() {
+			if visited[start] {
+				continue;
+			}
+			cycles += 1;
+			let mut pos = start;
+			while !visited[pos] {
+				visited[pos] = true;
+				pos = source[pos]?;
+			}
+		}
+		Some(moved.len() - cycles)
+	}
+
 	pub fn get_position_map(&self)
 	-> LayoutPosMap
 	{
@@ -270,18 +538,16 @@ impl Layout
 		LayoutPosMap(map)
 	}
 
-	fn shuffle_position() 
+	fn shuffle_position<R: Rng>(positions: &[usize], rng: &mut R)
 	-> (usize, usize)
 	{
-		let mut i = random::<usize>() % LAYOUT_MASK_NUM_SWAPPABLE;
-		let mut j = random::<usize>() % (LAYOUT_MASK_NUM_SWAPPABLE - 1);
+		let i = rng.gen::<usize>() % positions.len();
+		let mut j = rng.gen::<usize>() % (positions.len() - 1);
 		if j >= i {
 			j += 1;
 		}
-		i += LAYOUT_MASK_SWAP_OFFSETS[i];
-		j += LAYOUT_MASK_SWAP_OFFSETS[j];
 
-		(i, j)
+		(positions[i], positions[j])
 	}
 }
 
@@ -290,28 +556,15 @@ impl Layer
 	fn swap(&mut self, i: usize, j: usize)
 	{
 		let Layer(KeyMap(ref mut layer)) = *self;
-		let temp = layer[i];
-		layer[i] = layer[j];
-		layer[j] = temp;
+		layer.swap(i, j);
 	}
 
 	fn fill_position_map(&self, map: &mut [Option<KeyPress>; 128])
 	{
 		let Layer(KeyMap(ref layer)) = *self;
-		let KeyMap(ref fingers) = KEY_FINGERS;
-		let KeyMap(ref hands) = KEY_HANDS;
-		let KeyMap(ref rows) = KEY_ROWS;
-		let KeyMap(ref centers) = KEY_CENTER_COLUMN;
-		for (i, c) in layer.into_iter().enumerate() {
+		for (i, c) in layer.iter().enumerate() {
 			if *c < (128 as char) {
-				map[*c as usize] = Some(KeyPress {
-					kc: *c,
-					pos: i,
-					finger: fingers[i],
-					hand: hands[i],
-					row: rows[i],
-					center: centers[i],
-				});
+				map[*c as usize] = Some(key_press(*c, i));
 			}
 		}
 	}
@@ -319,6 +572,18 @@ impl Layer
 
 impl LayoutPosMap
 {
+	// Adds the keys of `other` that aren't in this map yet.
+	pub fn extend(&mut self, other: &LayoutPosMap)
+	{
+		let LayoutPosMap(ref mut map) = *self;
+		let LayoutPosMap(ref other) = *other;
+		for (kp, other_kp) in map.iter_mut().zip(other.iter()) {
+			if kp.is_none() {
+				*kp = *other_kp;
+			}
+		}
+	}
+
 	pub fn get_key_position(&self, kc: char)
 	-> &Option<KeyPress>
 	{
@@ -331,71 +596,119 @@ impl LayoutPosMap
 	}
 }
 
-impl LayoutPermutations
+impl LayoutShuffleMask
 {
-	pub fn new(layout: &Layout, depth: usize)
-	-> LayoutPermutations
+	// Stops the key at `pos` from being moved.
+	pub fn pin(&mut self, pos: usize)
 	{
-		let mut swaps = Vec::with_capacity(depth * 2);
-		for _ in 0..(depth * 2) {
-			swaps.push(0);
-		}
-		LayoutPermutations {
-			orig_layout: layout.clone(),
-			swap_idx: swaps,
-			started: false,
-		}
+		(self.0).0[pos] = false;
+	}
+
+	// The positions that may be swapped.
+	pub fn positions(&self)
+	-> Vec<usize>
+	{
+		let KeyMap(ref mask) = self.0;
+		(0..mask.len()).filter(|i| mask[*i]).collect()
 	}
 }
 
-impl Iterator for LayoutPermutations
+impl <'a> SwapNeighbourhood<'a>
 {
-	type Item = Layout;
+	pub fn new(layout: &'a Layout, mask: &LayoutShuffleMask, max_swaps: usize)
+	-> SwapNeighbourhood<'a>
+	{
+		SwapNeighbourhood {
+			layout,
+			positions: mask.positions(),
+			max_swaps,
+		}
+	}
 
-	fn next(&mut self)
-	-> Option<Layout>
+	// The number of positions a first cycle can start at, which split the
+	// rearrangements between them.
+	pub fn starts(&self)
+	-> usize
 	{
-		let mut some = false;
-		let mut idx = 0;
-		let mut val = 0;
-
-		if self.started {
-			for (i, e) in self.swap_idx.iter_mut().enumerate() {
-				if *e + 1 < LAYOUT_MASK_NUM_SWAPPABLE - i {
-					*e += 1;
-					some = true;
-					idx = i;
-					val = *e;
-					break;
-				}
-			}
-		} else {
-			self.started = true;
-			some = true;
-			idx = 1;
-			val = 0;
+		self.positions.len()
+	}
+
+	// Calls `f` with each rearrangement of the layout's keys whose first cycle
+	// starts at the `first`th movable position, always in the same order. A
+	// rearrangement is a list of cycles of positions, as taken by
+	// `Layout::rearranged`; a cycle of n positions takes n - 1 swaps.
+	//
+	// Each cycle starts at its lowest position, and cycles are in order of
+	// their first positions, so that no rearrangement is given twice. Keys
+	// that are the same, such as empty ones, are interchangeable: a cycle
+	// never holds two of them, which would give a layout that fewer swaps
+	// reach too.
+	pub fn for_each_from<F>(&self, first: usize, mut f: F)
+	where F: FnMut(&[Vec<usize>])
+	{
+		if self.max_swaps == 0 || first >= self.positions.len() {
+			return;
 		}
+		// A buffer for each cycle, reused for every rearrangement.
+		let mut cycles: Vec<Vec<usize>> = (0..self.max_swaps)
+			.map(|_| Vec::with_capacity(self.max_swaps + 1))
+			.collect();
+		let mut used = [false; 34];
+		self.start_cycle(&mut cycles, 0, &mut used, first, self.max_swaps, &mut f);
+	}
 
-		if some {
-			for i in 0..idx {
-				self.swap_idx[i] =  val + idx - i;
-			}
+	// Adds cycles, as the `depth`th and later, that start after the `first`th
+	// movable position.
+	fn add_cycles<F>(&self, cycles: &mut [Vec<usize>], depth: usize, used: &mut [bool; 34], first: usize, swaps: usize, f: &mut F)
+	where F: FnMut(&[Vec<usize>])
+	{
+		if swaps == 0 {
+			return;
+		}
+		for i in first..self.positions.len() {
+			self.start_cycle(cycles, depth, used, i, swaps, f);
+		}
+	}
 
-			let mut layout = self.orig_layout.clone();
-			let mut i = 0;
-			while i < self.swap_idx.len() {
-				let ref mut lower = ((layout.0).0).0;
-				let ref mut upper = ((layout.1).0).0;
-				let swap_left = self.swap_idx[i] + LAYOUT_MASK_SWAP_OFFSETS[self.swap_idx[i]];
-				let swap_right = self.swap_idx[i + 1] + LAYOUT_MASK_SWAP_OFFSETS[self.swap_idx[i + 1]];
-				lower.swap(swap_left, swap_right);
-				upper.swap(swap_left, swap_right);
-				i += 2;
-			}
+	// Starts the `depth`th cycle at the `i`th movable position.
+	fn start_cycle<F>(&self, cycles: &mut [Vec<usize>], depth: usize, used: &mut [bool; 34], i: usize, swaps: usize, f: &mut F)
+	where F: FnMut(&[Vec<usize>])
+	{
+		let start = self.positions[i];
+		if used[start] {
+			return;
+		}
+		used[start] = true;
+		cycles[depth].clear();
+		cycles[depth].push(start);
+		self.grow_cycle(cycles, depth, used, i, swaps, f);
+		used[start] = false;
+	}
 
-			Some(layout)
-		} else {
-			None
+	// Gives every rearrangement that has the `depth`th cycle as its last, and
+	// extends that cycle by positions after the `start`th.
+	fn grow_cycle<F>(&self, cycles: &mut [Vec<usize>], depth: usize, used: &mut [bool; 34], start: usize, swaps: usize, f: &mut F)
+	where F: FnMut(&[Vec<usize>])
+	{
+		let cost = cycles[depth].len() - 1;
+		if cost > 0 {
+			f(&cycles[..=depth]);
+			self.add_cycles(cycles, depth + 1, used, start + 1, swaps - cost, f);
+		}
+		if cost == swaps {
+			return;
+		}
+
+		let key = |pos: usize| (self.layout.lower()[pos], self.layout.upper()[pos]);
+		for &pos in self.positions[(start + 1)..].iter() {
+			if used[pos] || cycles[depth].iter().any(|&c| key(c) == key(pos)) {
+				continue;
+			}
+			used[pos] = true;
+			cycles[depth].push(pos);
+			self.grow_cycle(cycles, depth, used, start, swaps, f);
+			cycles[depth].pop();
+			used[pos] = false;
 		}
 	}
 }
@@ -415,7 +728,11 @@ impl fmt::Display for Layer
 	fn fmt(&self, f: &mut fmt::Formatter)
 	-> fmt::Result
 	{
-		let Layer(KeyMap(ref layer)) = *self;
+		let Layer(KeyMap(ref keys)) = *self;
+		let mut layer = ['\0'; 34];
+		for (label, kc) in layer.iter_mut().zip(keys.iter()) {
+			*label = key_label(*kc);
+		}
 		write!(f, "{} {} {} {} {} | {} {} {} {} {} {}
 {} {} {} {} {} | {} {} {} {} {} {}
 {} {} {} {} {} | {} {} {} {} {}
@@ -429,3 +746,72 @@ impl fmt::Display for Layer
 			layer[32], layer[33])
 	}
 }
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	use std::collections::HashSet;
+
+	// Every layout other than `layout` within `k` swaps of it, found by trying
+	// every swap of every layout found so far.
+	fn within_swaps(layout: &Layout, positions: &[usize], k: usize)
+	-> HashSet<Layout>
+	{
+		let mut found = HashSet::new();
+		found.insert(layout.clone());
+		let mut frontier = vec![layout.clone()];
+		for _ in 0..k {
+			let mut next = Vec::new();
+			for l in frontier.iter() {
+				for (n, &i) in positions.iter().enumerate() {
+					for &j in positions[(n + 1)..].iter() {
+						let swapped = l.rearranged(&[[i, j]]);
+						if found.insert(swapped.clone()) {
+							next.push(swapped);
+						}
+					}
+				}
+			}
+			frontier = next;
+		}
+		found.remove(layout);
+		found
+	}
+
+	#[test]
+	fn a_swap_neighbourhood_gives_every_layout_within_k_swaps_once()
+	{
+		// Two of the movable positions are empty, so their keys are the same.
+		let mut lower = *INIT_LAYOUT.lower();
+		let mut upper = *INIT_LAYOUT.upper();
+		for &pos in &[1, 13] {
+			lower[pos] = '\0';
+			upper[pos] = '\0';
+		}
+		let layout = Layout::new(lower, upper);
+		let mut mask = LAYOUT_MASK.clone();
+		let movable = [0, 1, 2, 12, 13, 23, 32];
+		for pos in 0..34 {
+			if !movable.contains(&pos) {
+				mask.pin(pos);
+			}
+		}
+
+		for k in 1..4 {
+			let neighbourhood = SwapNeighbourhood::new(&layout, &mask, k);
+			let mut found = HashSet::new();
+			let mut count = 0;
+			for first in 0..neighbourhood.starts() {
+				neighbourhood.for_each_from(first, |cycles| {
+					assert!(found.insert(layout.rearranged(cycles)), "{} swaps: {:?} twice", k, cycles);
+					count += 1;
+				});
+			}
+			let expected = within_swaps(&layout, &movable, k);
+			assert_eq!(count, expected.len(), "{} swaps", k);
+			assert!(found == expected, "{} swaps", k);
+		}
+	}
+}
@@ -0,0 +1,330 @@
+//! A self-describing layout file format, with validation.
+//!
+//! ```text
+//! // Comments start with two slashes.
+//! name = INIT
+//! geometry = split34
+//!
+//! [lower]
+//! j c y f k   z l , u q =
+//! r s t h d   m n a i o '
+//! / v g p b   x w . ; -
+//!         e   space
+//!
+//! [upper]
+//! J C Y F K   Z L < U Q +
+//! R S T H D   M N A I O "
+//! ? V G P B   X W > : _
+//!         E   space
+//!
+//! [pinned]
+//! e space
+//! ```
+//!
+//! Keys are separated by any amount of whitespace, and a lone `|` between the
+//! hands is ignored, so the output of the optimiser can be pasted in as is
+//! (write `pipe` to put the `|` key there). A key is a single character, a key
+//! glyph such as `⌫`, a key name such as `space` or `backspace`, or `none` for
+//! an unused key. If `[upper]` is left out, it is derived from `[lower]` using
+//! the US shift pairs. Keys listed in `[pinned]` are never moved by the
+//! optimiser.
+
+use std::collections::HashMap;
+
+use error::LayoutParseError;
+use layout;
+use layout::Layout;
+use layout::LayoutShuffleMask;
+
+pub static GEOMETRY: &str = "split34";
+
+// Number of keys in each row of the geometry, where each row starts in the
+// `KeyMap`, and how many keys of the row belong to the left hand.
+static ROW_LENGTHS: [usize; 4] = [11, 11, 10, 2];
+static ROW_STARTS:  [usize; 4] = [0, 11, 22, 32];
+static ROW_SPLITS:  [usize; 4] = [5, 5, 5, 1];
+
+pub struct LayoutFile
+{
+	pub layout: Layout,
+	pub mask:   LayoutShuffleMask,
+}
+
+// Whether `s` looks like this format rather than the legacy fixed-column one.
+pub fn is_layout_file(s: &str)
+-> bool
+{
+	s.lines().any(|line| line.trim() == "[lower]")
+}
+
+pub fn parse(s: &str)
+-> Result<LayoutFile, LayoutParseError>
+{
+	let mut geometry = GEOMETRY.to_string();
+	let mut section: Option<String> = None;
+	let mut rows: HashMap<String, Vec<(usize, Vec<char>)>> = HashMap::new();
+	let mut pinned: Vec<(usize, char)> = Vec::new();
+	let mut sections: Vec<String> = Vec::new();
+
+	for (i, line) in s.lines().enumerate() {
+		let line_no = i + 1;
+		let tokens: Vec<&str> = line.split_whitespace().collect();
+		if tokens.is_empty() || tokens[0].starts_with("//") {
+			continue;
+		}
+
+		// [section]
+		let trimmed = line.trim();
+		if trimmed.len() > 2 && trimmed.starts_with('[') && trimmed.ends_with(']') &&
+		   trimmed[1..(trimmed.len() - 1)].chars().all(|c| c.is_ascii_lowercase()) {
+			let name = &trimmed[1..(trimmed.len() - 1)];
+			if name != "lower" && name != "upper" && name != "pinned" {
+				return Err(error(line_no, format!("unknown section [{}]", name)));
+			}
+			if sections.iter().any(|s| s == name) {
+				return Err(error(line_no, format!("duplicate section [{}]", name)));
+			}
+			sections.push(name.to_string());
+			if name != "pinned" {
+				rows.insert(name.to_string(), Vec::new());
+			}
+			section = Some(name.to_string());
+			continue;
+		}
+
+		let section = match section {
+			Some(ref section) => section,
+			None => {
+				// key = value
+				if tokens.len() < 3 || tokens[1] != "=" {
+					return Err(error(line_no, format!("expected `key = value` or a section, found {:?}", trimmed)));
+				}
+				let value = tokens[2..].join(" ");
+				match tokens[0] {
+					"name" => (),
+					"geometry" => geometry = value,
+					key => return Err(error(line_no, format!("unknown setting {:?}", key))),
+				}
+				continue;
+			},
+		};
+
+		// Allow a `|` between the hands, as printed by the optimiser; the `|`
+		// key itself is written `pipe`, so it can't be mistaken for one.
+		let mut tokens = tokens;
+		if let Some(row) = rows.get(section).map(|r| r.len()) {
+			if row < ROW_SPLITS.len() && tokens.get(ROW_SPLITS[row]) == Some(&"|") {
+				tokens.remove(ROW_SPLITS[row]);
+			}
+		}
+
+		let mut keys = Vec::with_capacity(tokens.len());
+		for token in tokens.iter() {
+			keys.push(parse_key(token).map_err(|e| error(line_no, e))?);
+		}
+		if section == "pinned" {
+			pinned.extend(keys.into_iter().map(|k| (line_no, k)));
+		} else {
+			rows.get_mut(section).unwrap().push((line_no, keys));
+		}
+	}
+
+	if geometry != GEOMETRY {
+		return Err(error(0, format!("unknown geometry {:?}; the only supported geometry is {:?}", geometry, GEOMETRY)));
+	}
+
+	let lower = match rows.get("lower") {
+		Some(rows) => layer_from_rows("lower", rows)?,
+		None => return Err(error(0, "missing [lower] section".to_string())),
+	};
+	let upper = match rows.get("upper") {
+		Some(rows) => layer_from_rows("upper", rows)?,
+		None => {
+			let mut upper = lower;
+			for kc in upper.iter_mut() {
+				*kc = layout::shifted(*kc);
+			}
+			upper
+		},
+	};
+	check_duplicates(&lower, &upper, &rows)?;
+
+	let layout = Layout::new(lower, upper);
+	let mut mask = layout::LAYOUT_MASK.clone();
+	for (line_no, kc) in pinned {
+		let pos = lower.iter().position(|k| *k == kc)
+			.or_else(|| upper.iter().position(|k| *k == kc));
+		match pos {
+			Some(pos) if kc != '\0' => mask.pin(pos),
+			_ => return Err(error(line_no, format!("pinned key {} is not on the layout", describe(kc)))),
+		}
+	}
+
+	Ok(LayoutFile {
+		layout,
+		mask,
+	})
+}
+
+// Writes `layout` in this format, with both layers spelled out.
+pub fn render(layout: &Layout, name: &str)
+-> String
+{
+	let mut s = String::new();
+	s.push_str(&format!("name = {}\ngeometry = {}\n", name, GEOMETRY));
+	for (section, keys) in [("lower", layout.lower()), ("upper", layout.upper())].iter() {
+		let tokens: Vec<String> = keys.iter().map(|kc| key_token(*kc)).collect();
+		// Align the letter rows; the thumb keys are often named, and so wider.
+		let width = tokens[..ROW_STARTS[3]].iter().map(|t| t.chars().count()).max().unwrap_or(1);
+		s.push_str(&format!("\n[{}]\n", section));
+		for row in 0..ROW_LENGTHS.len() {
+			let row_keys = &tokens[ROW_STARTS[row]..(ROW_STARTS[row] + ROW_LENGTHS[row])];
+			let cells = |keys: &[String]| -> String {
+				keys.iter().map(|k| format!("{:<w$}", k, w = width)).collect::<Vec<_>>().join(" ")
+			};
+			// Thumb keys sit under the inner columns.
+			let indent = if row == ROW_LENGTHS.len() - 1 { " ".repeat((width + 1) * 4) } else { String::new() };
+			let line = format!("{}{}   {}", indent,
+				cells(&row_keys[..ROW_SPLITS[row]]), cells(&row_keys[ROW_SPLITS[row]..]));
+			s.push_str(line.trim_end());
+			s.push('\n');
+		}
+	}
+	s
+}
+
+fn layer_from_rows(section: &str, rows: &[(usize, Vec<char>)])
+-> Result<[char; 34], LayoutParseError>
+{
+	if rows.len() != ROW_LENGTHS.len() {
+		let line = rows.last().map(|r| r.0).unwrap_or(0);
+		return Err(error(line, format!("[{}] has {} rows, expected {}", section, rows.len(), ROW_LENGTHS.len())));
+	}
+
+	let mut layer = ['\0'; 34];
+	for (row, &(line_no, ref keys)) in rows.iter().enumerate() {
+		let expected = ROW_LENGTHS[row];
+		if keys.len() != expected {
+			return Err(error(line_no, format!("row {} of [{}] has {} keys, expected {}",
+				row + 1, section, keys.len(), expected)));
+		}
+		layer[ROW_STARTS[row]..(ROW_STARTS[row] + expected)].copy_from_slice(keys);
+	}
+
+	Ok(layer)
+}
+
+// A key may appear only once per layer, and only at the same position in both
+// layers (like the space bar).
+fn check_duplicates(
+	lower: &[char; 34],
+	upper: &[char; 34],
+	rows:  &HashMap<String, Vec<(usize, Vec<char>)>>)
+-> Result<(), LayoutParseError>
+{
+	let line_of = |section: &str, pos: usize| -> usize {
+		let row = ROW_STARTS.iter().rposition(|start| *start <= pos).unwrap();
+		rows.get(section).and_then(|r| r.get(row)).map(|r| r.0).unwrap_or(0)
+	};
+
+	let mut seen: HashMap<char, (&str, usize)> = HashMap::new();
+	for (section, layer) in [("lower", lower), ("upper", upper)].iter() {
+		for (pos, kc) in layer.iter().enumerate() {
+			if *kc == '\0' {
+				continue;
+			}
+			if let Some(&(other_section, other_pos)) = seen.get(kc) {
+				if other_section == *section || other_pos != pos {
+					return Err(error(line_of(section, pos), format!("duplicate key {} in [{}] (also in [{}] on line {})",
+						describe(*kc), section, other_section, line_of(other_section, other_pos))));
+				}
+			}
+			seen.insert(*kc, (section, pos));
+		}
+	}
+
+	Ok(())
+}
+
+pub fn parse_key(token: &str)
+-> Result<char, String>
+{
+	let mut chars = token.chars();
+	match (chars.next(), chars.next()) {
+		(Some(c), None) => Ok(layout::key_from_label(c)),
+		_ if token.eq_ignore_ascii_case("none") => Ok('\0'),
+		_ if token.eq_ignore_ascii_case("pipe") => Ok('|'),
+		_ => layout::named_key(token).ok_or_else(|| format!("unknown key {:?}", token)),
+	}
+}
+
+// How a key is written in a layout file.
+pub fn key_token(kc: char)
+-> String
+{
+	match kc {
+		'\0' => "none".to_string(),
+		' ' => "space".to_string(),
+		'|' => "pipe".to_string(),
+		_ => layout::key_label(kc).to_string(),
+	}
+}
+
+fn describe(kc: char)
+-> String
+{
+	format!("{:?}", key_token(kc))
+}
+
+// An error on line `line`, or in the file as a whole if that is 0.
+fn error(line: usize, message: String)
+-> LayoutParseError
+{
+	if line == 0 {
+		LayoutParseError::new(message)
+	} else {
+		LayoutParseError::at_line(line, message)
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	fn round_trip(layout: &Layout)
+	{
+		let parsed = match parse(&render(layout, "test")) {
+			Ok(file) => file.layout,
+			Err(e) => panic!("{}", e),
+		};
+		assert_eq!(parsed.lower(), layout.lower());
+		assert_eq!(parsed.upper(), layout.upper());
+	}
+
+	#[test]
+	fn round_trips_init()
+	{
+		round_trip(&layout::INIT_LAYOUT);
+	}
+
+	#[test]
+	fn round_trips_pipe_after_the_hand_separator()
+	{
+		let mut lower = *layout::INIT_LAYOUT.lower();
+		let mut upper = *layout::INIT_LAYOUT.upper();
+		lower[ROW_STARTS[0] + ROW_SPLITS[0]] = '|';
+		upper[ROW_STARTS[0] + ROW_SPLITS[0]] = '\\';
+		round_trip(&Layout::new(lower, upper));
+	}
+
+	#[test]
+	fn ignores_a_lone_bar_between_the_hands()
+	{
+		let file = "[lower]\nj c y f k | z l , u q =\nr s t h d | m n a i o '\n/ v g p b | x w . ; -\ne | space\n";
+		match parse(file) {
+			Ok(file) => assert_eq!(file.layout.lower(), layout::INIT_LAYOUT.lower()),
+			Err(e) => panic!("{}", e),
+		}
+	}
+}
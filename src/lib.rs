@@ -0,0 +1,113 @@
+//! Finding keyboard layouts that are comfortable to type a corpus on.
+//!
+//! The library covers the whole pipeline that the `keygen` program runs:
+//!
+//! - `layout`: layouts, their positions and the keys on them, with
+//!   `registry` for the built-in layouts, and `layoutfile` and `import` for
+//!   reading layouts from files;
+//! - `corpus` and `cache`: reading a corpus into a `QuartadList`, the counts
+//!   of every run of up to four keys that the penalties are calculated from;
+//! - `penalty`: scoring a layout against a corpus with `calculate_penalty`;
+//! - `optimizer`: optimising a layout, with `annealing`, `tempering`, `tabu`,
+//!   `genetic` and `hillclimb` the ways to do it, `best` keeping the best
+//!   layouts found and `simulator` printing them;
+//! - `error`: the errors that all of these report;
+//! - `export`, `output`, `compare`, `stats`, `svg`, `report` and `xml`:
+//!   presenting layouts and results.
+//!
+//! The modules are private; what the program and other users of the library
+//! need is exported below. The commands themselves, which print their results,
+//! belong to the program.
+
+mod error;
+mod layout;
+mod penalty;
+mod optimizer;
+mod annealing;
+mod tabu;
+mod genetic;
+mod tempering;
+mod hillclimb;
+mod simulator;
+mod best;
+mod corpus;
+mod cache;
+mod stats;
+mod wordlist;
+mod keylog;
+mod layoutfile;
+mod export;
+mod qmk;
+mod zmk;
+mod kanata;
+mod xkb;
+mod klc;
+mod keylayout;
+mod import;
+mod kle;
+mod registry;
+mod compare;
+mod output;
+mod svg;
+mod xml;
+mod report;
+
+pub use error::Error;
+pub use error::Result;
+
+// Layouts, and scoring them.
+pub use layout::Layout;
+pub use layout::LayoutPosMap;
+pub use layout::LayoutShuffleMask;
+pub use penalty::calculate_penalty;
+pub use penalty::init as penalties;
+pub use penalty::KeyPenalty;
+pub use penalty::KeyPenaltyResult;
+pub use registry::find as find_layout;
+pub use registry::names as layout_names;
+pub use registry::builtin_names as builtin_layout_names;
+pub use registry::default_dir as default_layouts_dir;
+pub use registry::NamedLayout;
+
+// Optimising layouts.
+pub use optimizer::run as optimize;
+pub use optimizer::Optimizer;
+pub use optimizer::Problem;
+pub use optimizer::Progress;
+pub use optimizer::Strategy;
+pub use best::BestLayouts;
+pub use simulator::simulate;
+pub use simulator::refine;
+
+// Reading corpora.
+pub use penalty::quartads_from_str;
+pub use penalty::QuartadCounter;
+pub use penalty::QuartadList;
+pub use corpus::parse_format as parse_corpus_format;
+pub use corpus::CorpusFormat;
+pub use wordlist::WordListConfig;
+pub use cache::read_quartads;
+pub use cache::default_dir as default_cache_dir;
+pub use cache::CacheMode;
+pub use stats::read as read_corpus_stats;
+pub use stats::CorpusStats;
+
+// Presenting results.
+pub use output::parse_format as parse_output_format;
+pub use output::Json;
+pub use output::Output;
+pub use output::OutputFormat;
+pub use output::Scored;
+pub use simulator::print_result;
+pub use compare::output_comparison;
+pub use stats::output_stats as output_corpus_stats;
+pub use svg::heatmap;
+pub use svg::parse_heat;
+pub use svg::Heat;
+pub use report::report;
+pub use export::export;
+pub use export::encode as encode_export;
+pub use export::parse_format as parse_export_format;
+pub use export::parse_matrix;
+pub use export::ExportFormat;
+pub use export::ExportOptions;
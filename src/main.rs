@@ -1,202 +1,239 @@
- #![feature(linked_list_cursors)]
-
-mod layout;
-mod penalty;
-mod annealing;
-mod simulator;
+//! The `keygen` program: parses its options and runs one of the commands.
 
+extern crate keygen;
 extern crate getopts;
 
+mod commands;
+
 use std::env;
 use std::fs::File;
-use std::io::Read;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
 use getopts::Options;
 
+use keygen::CacheMode;
+use keygen::Error;
+use keygen::Heat;
+use keygen::Json;
+use keygen::Output;
+use keygen::OutputFormat;
+use keygen::Result;
+use keygen::Strategy;
+
+static COMMANDS: [&str; 10] = [
+	"run", "run-ref", "analyze", "compare", "refine", "corpus-stats",
+	"heatmap", "report", "export", "list-layouts",
+];
+
 fn main()
+{
+	let args: Vec<String> = env::args().collect();
+	if let Err(e) = run_cli(&args) {
+		eprintln!("Error: {}", e);
+		process::exit(e.exit_code());
+	}
+}
+
+fn run_cli(args: &[String])
+-> Result<()>
 {
 	let mut opts = Options::new();
 	opts.optflag("h", "help", "print this help menu");
 	opts.optflag("d", "debug", "show debug logging");
-	opts.optopt("t", "top", "number of top layouts to print (default: 1)", "TOP_LAYOUTS");
+	opts.optopt("t", "top", "number of top layouts to print (default: 1), or of n-grams for corpus-stats (default: 20), compare and report (default: 10)", "TOP_LAYOUTS");
 	opts.optopt("s", "swaps-per-iteration", "maximum number of swaps per iteration (default: 3)", "SWAPS");
+	opts.optopt("", "optimizer", "optimiser for run: annealing, tabu, genetic or tempering (default: annealing)", "OPTIMIZER");
+	opts.optopt("", "tenure", "steps for which a tabu search may not move a key back (default: 10)", "STEPS");
+	opts.optopt("", "steps", "steps of each tabu search (default: 500) or parallel tempering run (default: 2000)", "STEPS");
+	opts.optopt("", "replicas", "replicas of a parallel tempering run (default: 8)", "REPLICAS");
+	opts.optopt("", "population", "layouts in each generation of a genetic search (default: 50)", "LAYOUTS");
+	opts.optopt("", "generations", "generations of each genetic search (default: 200)", "GENERATIONS");
+	opts.optopt("", "min-distance", "minimum number of swaps between the top layouts (default: 1, so that each is distinct)", "SWAPS");
+	opts.optflag("", "no-cache", "neither read nor write the n-gram cache");
+	opts.optflag("", "rebuild-cache", "ignore the n-gram cache and rebuild it");
+	opts.optopt("", "cache-dir", "directory for cached n-gram tables (default: ~/.cache/keygen)", "DIR");
+	opts.optopt("", "layouts-dir", "directory of layout files that can be named instead of given as a path (default: ~/.config/keygen/layouts)", "DIR");
+	opts.optopt("", "corpus-format", "format of the corpus: text, words or keylog (default: text)", "FORMAT");
+	opts.optopt("", "word-sep", "text between words of a words corpus (default: a space)", "SEP");
+	opts.optmulti("", "punct", "punctuation that follows a word of a words corpus with the given probability, e.g. .:0.05", "MARK:P");
+	opts.optopt("", "format", "output format: text, json or csv (default: text); for export: qmk, zmk, kanata, kmonad, xkb, klc, keylayout or layout (default: qmk)", "FORMAT");
+	opts.optopt("", "seed", "seed of the first run, which later runs count up from (default: random)", "SEED");
+	opts.optopt("", "name", "name of the exported layout (default: the layout file name)", "NAME");
+	opts.optopt("", "matrix", "physical key order for qmk and zmk export: layout positions 0-33 or _ for unused keys, rows separated by ; or newlines, or a file holding them", "SPEC");
+	opts.optopt("", "layout-macro", "QMK layout macro of the keyboard (default: LAYOUT)", "MACRO");
+	opts.optopt("o", "output", "write the export, heat map or report to a file instead of stdout", "FILE");
+	opts.optopt("", "heat", "what colours the keys of a heat map: frequency or penalty (default: frequency)", "HEAT");
+	opts.optopt("", "arcs", "number of the costliest same-finger bigrams to draw on a heat map (default: 0)", "ARCS");
 
-	let args: Vec<String> = env::args().collect();
 	let progname = &args[0];
 	if args.len() < 2 {
 		print_usage(progname, opts);
-		return;
+		return Ok(());
 	}
 	let command = &args[1];
-	let matches = match opts.parse(&args[2..]) {
-		Ok(m) => { m }
-		Err(f) => { panic!(f.to_string()) }
-	};
+	let matches = opts.parse(&args[2..]).map_err(|f| Error::config(f.to_string()))?;
 
 	// --help
 	if matches.opt_present("h") {
 		print_usage(progname, opts);
-		return;
+		return Ok(());
+	}
+	if !COMMANDS.contains(&&command[..]) {
+		return Err(Error::config(format!("unknown command {}; see {} --help", command, progname)));
 	}
 
-	// Read corpus.
-	let corpus_filename = match matches.free.get(0) {
-		Some(f) => f,
-		None => {
-			print_usage(progname, opts);
-			return;
-		},
+	let layouts_dir = match matches.opt_str("layouts-dir") {
+		Some(dir) => PathBuf::from(dir),
+		None => keygen::default_layouts_dir(),
 	};
-	let mut f = match File::open(corpus_filename) {
-		Ok(f) => f,
-		Err(e) => {
-			println!("Error: {}", e);
-			panic!("could not read corpus");
+
+	// Exporting needs a layout but no corpus.
+	if command == "export" {
+		return export(&matches, &layouts_dir);
+	}
+
+	let output_format = match matches.opt_str("format") {
+		None => OutputFormat::Text,
+		Some(f) => match keygen::parse_output_format(&f) {
+			Some(f) => f,
+			None => return Err(Error::config(format!("unknown output format {}", f))),
 		},
 	};
-	let mut corpus = String::new();
-	match f.read_to_string(&mut corpus) {
-		Ok(_) => (),
-		Err(e) => {
-			println!("Error: {}", e);
-			panic!("could not read corpus");
-		}
-	};
+	let mut metadata = vec![("command", Json::from(&command[..]))];
 
-	// Read layout, if applicable.
-	let _layout;
-	let layout = match matches.free.get(1) {
-		None => &layout::INIT_LAYOUT,
-		Some(layout_filename) => {
-			let mut f = match File::open(layout_filename) {
-				Ok(f) => f,
-				Err(e) => {
-					println!("Error: {}", e);
-					panic!("could not read layout");
-				}
-			};
-			let mut layout_str = String::new();
-			match f.read_to_string(&mut layout_str) {
-				Ok(_) => (),
-				Err(e) => {
-					println!("Error: {}", e);
-					panic!("could not read layout");
-				}
-			};
-			_layout = layout::Layout::from_string(&layout_str[..]);
-			&_layout
-		},
+	if command == "list-layouts" {
+		commands::list_layouts(&Output::new(output_format, metadata), &layouts_dir);
+		return Ok(());
+	}
+
+	// Read corpus. This may be a file, a directory or a glob of files.
+	let corpus_filename = match matches.free.first() {
+		Some(f) => f,
+		None => return Err(Error::config(format!("{} needs a corpus; see {} --help", command, progname))),
 	};
+	metadata.push(("corpus", Json::from(&corpus_filename[..])));
+
+	// Read layouts.
+	let layouts = commands::find_layouts(command, &matches.free[1..], &layouts_dir)?;
+	let layout = &layouts[0].layout;
+	let mask = &layouts[0].mask;
 
 	// Parse options.
 	let debug = matches.opt_present("d");
-	let top   = numopt(matches.opt_str("t"), 1usize);
-	let swaps = numopt(matches.opt_str("s"), 3usize);
+	let top   = numopt(&matches, "top", 1usize)?;
+	let swaps = numopt(&matches, "swaps-per-iteration", 3usize)?;
+	let min_distance = numopt(&matches, "min-distance", 1usize)?;
+	if top == 0 || swaps == 0 {
+		return Err(Error::config("--top and --swaps-per-iteration must be at least 1"));
+	}
+	let strategy = Strategy::parse(matches.opt_str("optimizer").as_ref().map(|o| &o[..]),
+		|name, default| numopt(&matches, name, default))?;
+	let format = keygen::parse_corpus_format(matches.opt_str("corpus-format").as_ref().map(|f| &f[..]),
+		matches.opt_str("word-sep"), &matches.opt_strs("punct"))?;
+
+	if command == "corpus-stats" {
+		let output = Output::new(output_format, metadata);
+		return commands::corpus_stats(corpus_filename, &format, layout, numopt(&matches, "top", 20usize)?, &output);
+	}
+
+	let cache_mode = if matches.opt_present("no-cache") {
+		CacheMode::Disabled
+	} else if matches.opt_present("rebuild-cache") {
+		CacheMode::Rebuild
+	} else {
+		CacheMode::Use
+	};
+	let cache_dir = match matches.opt_str("cache-dir") {
+		Some(dir) => PathBuf::from(dir),
+		None => keygen::default_cache_dir(),
+	};
+	let (quartads, len) = keygen::read_quartads(corpus_filename, &format, &layouts, &cache_dir, cache_mode)?;
+
+	metadata.push(("keystrokes", Json::from(len)));
+	if command == "run" || command == "refine" {
+		metadata.push(("swaps", Json::from(swaps)));
+		metadata.push(("min_distance", Json::from(min_distance)));
+	}
+	if command == "run" {
+		metadata.extend(strategy.metadata());
+	}
+	let output = Output::new(output_format, metadata);
 
 	match command.as_ref() {
-		"run" => run(&corpus[..], layout, debug, top, swaps),
-		"run-ref" => run_ref(&corpus[..]),
-		"refine" => refine(&corpus[..], layout, debug, top, swaps),
+		"run" => {
+			let seed = match matches.opt_str("seed") {
+				None => None,
+				Some(_) => Some(numopt(&matches, "seed", 0u64)?),
+			};
+			commands::run(&quartads, len, layout, mask, debug, top, &strategy, swaps, min_distance, seed, &output);
+		},
+		"run-ref" | "analyze" => commands::analyze(&quartads, len, &layouts, &output),
+		"compare" => commands::compare(&quartads, len, &layouts, numopt(&matches, "top", 10usize)?, &output),
+		"refine" => commands::refine(&quartads, len, layout, mask, debug, top, swaps, min_distance, &output),
+		"heatmap" => {
+			let heat = match matches.opt_str("heat") {
+				None => Heat::Frequency,
+				Some(h) => match keygen::parse_heat(&h) {
+					Some(h) => h,
+					None => return Err(Error::config(format!("unknown heat map {}", h))),
+				},
+			};
+			let svg = keygen::heatmap(&quartads, len, &layouts[0], heat, numopt(&matches, "arcs", 0usize)?);
+			write_output(&matches, svg.as_bytes())?;
+		},
+		"report" => {
+			let html = keygen::report(&quartads, len, &layouts[0], corpus_filename, numopt(&matches, "top", 10usize)?);
+			write_output(&matches, html.as_bytes())?;
+		},
 		_ => print_usage(progname, opts),
 	};
+	Ok(())
 }
 
-fn run(s: &str, layout: &layout::Layout, debug: bool, top: usize, swaps: usize)
+// Writes to the file given by --output, or else to stdout.
+fn write_output(matches: &getopts::Matches, output: &[u8])
+-> Result<()>
 {
-	let penalties = penalty::init();
-	let init_pos_map = layout::INIT_LAYOUT.get_position_map();
-	let quartads = penalty::prepare_quartad_list(s, &init_pos_map);
-	let len = s.len();
-
-	loop {
-		simulator::simulate(&quartads, len, layout, &penalties, debug, top, swaps);
+	match matches.opt_str("o") {
+		None => io::stdout().write_all(output).map_err(Error::from),
+		Some(filename) => File::create(&filename)
+			.and_then(|mut f| f.write_all(output))
+			.map_err(|e| Error::io(e, Path::new(&filename))),
 	}
 }
 
-fn run_ref(s: &str)
+fn export(matches: &getopts::Matches, layouts_dir: &Path)
+-> Result<()>
 {
-	let penalties = penalty::init();
-	let init_pos_map = layout::INIT_LAYOUT.get_position_map();
-	let quartads = penalty::prepare_quartad_list(s, &init_pos_map);
-	let len = s.len();
-
-	let penalty = penalty::calculate_penalty(&quartads, len, &layout::QWERTY_LAYOUT, &penalties, true);
-	println!("Reference: QWERTY");
-	simulator::print_result(&layout::QWERTY_LAYOUT, &penalty);
-	println!("");
-
-	let penalty = penalty::calculate_penalty(&quartads, len, &layout::DVORAK_LAYOUT, &penalties, true);
-	println!("Reference: DVORAK");
-	simulator::print_result(&layout::DVORAK_LAYOUT, &penalty);
-	println!("");
-
-	let penalty = penalty::calculate_penalty(&quartads, len, &layout::COLEMAK_LAYOUT, &penalties, true);
-	println!("Reference: COLEMAK");
-	simulator::print_result(&layout::COLEMAK_LAYOUT, &penalty);
-	println!("");
-
-	let penalty = penalty::calculate_penalty(&quartads, len, &layout::QGMLWY_LAYOUT, &penalties, true);
-	println!("Reference: QGMLWY");
-	simulator::print_result(&layout::QGMLWY_LAYOUT, &penalty);
-	println!("");
-
-	let penalty = penalty::calculate_penalty(&quartads, len, &layout::WORKMAN_LAYOUT, &penalties, true);
-	println!("Reference: WORKMAN");
-	simulator::print_result(&layout::WORKMAN_LAYOUT, &penalty);
-	println!("");
-
-	let penalty = penalty::calculate_penalty(&quartads, len, &layout::MALTRON_LAYOUT, &penalties, true);
-	println!("Reference: MALTRON");
-	simulator::print_result(&layout::MALTRON_LAYOUT, &penalty);
-	println!("");
-
-	let penalty = penalty::calculate_penalty(&quartads, len, &layout::MTGAP_LAYOUT, &penalties, true);
-	println!("Reference: MTGAP");
-	simulator::print_result(&layout::MTGAP_LAYOUT, &penalty);
-	println!("");
-
-	let penalty = penalty::calculate_penalty(&quartads, len, &layout::CAPEWELL_LAYOUT, &penalties, true);
-	println!("Reference: CAPEWELL");
-	simulator::print_result(&layout::CAPEWELL_LAYOUT, &penalty);
-	println!("");
-
-	let penalty = penalty::calculate_penalty(&quartads, len, &layout::ARENSITO_LAYOUT, &penalties, true);
-	println!("Reference: ARENSITO");
-	simulator::print_result(&layout::ARENSITO_LAYOUT, &penalty);
-	println!("");
-
-	let penalty = penalty::calculate_penalty(&quartads, len, &layout::INIT_LAYOUT, &penalties, true);
-	println!("Reference: INITIAL");
-	simulator::print_result(&layout::INIT_LAYOUT, &penalty);
-}
-
-fn refine(s: &str, layout: &layout::Layout, debug: bool, top: usize, swaps: usize)
-{
-	let penalties = penalty::init();
-	let init_pos_map = layout::INIT_LAYOUT.get_position_map();
-	let quartads = penalty::prepare_quartad_list(s, &init_pos_map);
-	let len = s.len();
+	let spec = matches.free.first().map(|s| &s[..]).unwrap_or("init");
+	let format_name = matches.opt_str("format").unwrap_or_else(|| "qmk".to_string());
+	let format = match keygen::parse_export_format(&format_name) {
+		Some(f) => f,
+		None => return Err(Error::config(format!("unknown export format {}", format_name))),
+	};
 
-	simulator::refine(&quartads, len, layout, &penalties, debug, top, swaps);
+	let output = commands::export(spec, layouts_dir, format, matches.opt_str("name"),
+		matches.opt_str("matrix"), matches.opt_str("layout-macro"), matches.opt_present("o"))?;
+	write_output(matches, &output)
 }
 
 fn print_usage(progname: &String, opts: Options)
 {
-	let brief = format!("Usage: {} (run|run-ref) <corpus> [OPTIONS]", progname);
+	let brief = format!("Usage: {p} (run|refine|corpus-stats|heatmap|report) <corpus> [<layout>] [OPTIONS]\n       {p} (analyze|run-ref) <corpus> [<layout>...] [OPTIONS]\n       {p} compare <corpus> <layout> <layout>... [OPTIONS]\n       {p} export [<layout>] [OPTIONS]\n       {p} list-layouts [OPTIONS]\n\n<corpus> may be a file, a directory or a quoted glob such as 'dumps/*.txt'.\n<layout> may be a layout file or the name of a built-in layout or of a file in the layouts directory.", p = progname);
 	print!("{}", opts.usage(&brief));
 }
 
-fn numopt<T>(s: Option<String>, default: T)
--> T
-where T: std::str::FromStr + std::fmt::Display
+// Parses the value of option `name`, if it was given.
+fn numopt<T>(matches: &getopts::Matches, name: &str, default: T)
+-> Result<T>
+where T: std::str::FromStr
 {
-	match s {
-		None => default,
-		Some(num) => match num.parse::<T>() {
-			Ok(n) => n,
-			Err(_) => {
-				println!("Error: invalid option value {}. Using default value {}.", num, default);
-				default
-			},
-		},
+	match matches.opt_str(name) {
+		None => Ok(default),
+		Some(num) => num.parse::<T>()
+			.map_err(|_| Error::config(format!("invalid value {} for --{}", num, name))),
 	}
-}
\ No newline at end of file
+}
@@ -0,0 +1,184 @@
+//! What every optimiser has in common: the problem it solves, and the steps it
+//! takes to solve it.
+
+extern crate rand;
+
+use self::rand::SeedableRng;
+use self::rand::StdRng;
+
+use annealing;
+use best::BestLayouts;
+use error::Error;
+use error::Result;
+use genetic;
+use layout::Layout;
+use layout::LayoutShuffleMask;
+use output::Json;
+use penalty;
+use penalty::KeyPenalty;
+use penalty::QuartadList;
+use tabu;
+use tempering;
+
+// A corpus to find a layout for, and the rules to score layouts by.
+#[derive(Clone, Copy)]
+pub struct Problem<'a>
+{
+	pub quartads:  &'a QuartadList,
+	pub len:        usize,
+	pub mask:      &'a LayoutShuffleMask,
+	pub penalties: &'a [KeyPenalty],
+}
+
+impl <'a> Problem<'a>
+{
+	// A layout's scaled penalty.
+	pub fn penalty(&self, layout: &Layout)
+	-> f64
+	{
+		penalty::calculate_penalty(self.quartads, self.len, layout, self.penalties, false).1
+	}
+}
+
+// How far an optimiser has got: the steps it has taken, and the penalty of the
+// layout it has got to.
+pub struct Progress
+{
+	pub step:    usize,
+	pub penalty: f64,
+}
+
+pub trait Optimizer
+{
+	// The name to log its progress under.
+	fn name(&self)
+	-> &'static str;
+
+	// Starts from `layout`, whose penalty is `penalty`.
+	fn init(&mut self, layout: &Layout, penalty: f64);
+
+	// Takes a step, offering the layouts it finds to the best layouts, or
+	// returns false if it has finished.
+	fn step(&mut self)
+	-> bool;
+
+	// The best layouts found so far, best first.
+	fn best(&self)
+	-> &BestLayouts;
+
+	fn progress(&self)
+	-> Progress;
+}
+
+// Runs `optimizer` from `layout` until it has finished, calling `on_step`
+// after each step and logging its progress to stderr if `debug`.
+pub fn run<F>(optimizer: &mut dyn Optimizer, layout: &Layout, penalty: f64, debug: bool, mut on_step: F)
+where F: FnMut(&dyn Optimizer)
+{
+	optimizer.init(layout, penalty);
+	while optimizer.step() {
+		if debug {
+			let progress = optimizer.progress();
+			eprintln!("{} step {}: {}", optimizer.name(), progress.step, progress.penalty);
+		}
+		on_step(optimizer);
+	}
+}
+
+// A random number generator that gives the same numbers for the same seed.
+pub fn seeded_rng(seed: u64)
+-> StdRng
+{
+	SeedableRng::from_seed(&[(seed & 0xffff_ffff) as usize, (seed >> 32) as usize][..])
+}
+
+// How `run` searches for layouts.
+pub enum Strategy
+{
+	Annealing,
+	Tabu { tenure: usize, steps: usize },
+	Genetic { population: usize, generations: usize },
+	Tempering { replicas: usize, steps: usize },
+}
+
+impl Strategy
+{
+	// The strategy named `name`, by default annealing. `option` gives the
+	// value of a numeric option such as "steps", or the default it is passed.
+	pub fn parse<F>(name: Option<&str>, mut option: F)
+	-> Result<Strategy>
+	where F: FnMut(&str, usize) -> Result<usize>
+	{
+		let strategy = match name {
+			None | Some("annealing") => Strategy::Annealing,
+			Some("tabu") => Strategy::Tabu {
+				tenure: option("tenure", tabu::TENURE)?,
+				steps:  option("steps", tabu::STEPS)?,
+			},
+			Some("genetic") => Strategy::Genetic {
+				population:  option("population", genetic::POPULATION)?,
+				generations: option("generations", genetic::GENERATIONS)?,
+			},
+			Some("tempering") => Strategy::Tempering {
+				replicas: option("replicas", tempering::REPLICAS)?,
+				steps:    option("steps", tempering::STEPS)?,
+			},
+			Some(o) => return Err(Error::config(format!("unknown optimizer {}", o))),
+		};
+		match strategy {
+			Strategy::Genetic { population: 0, .. } =>
+				Err(Error::config("--population must be at least 1")),
+			Strategy::Tempering { replicas: 0, .. } =>
+				Err(Error::config("--replicas must be at least 1")),
+			_ => Ok(strategy),
+		}
+	}
+
+	// The optimiser that runs with `seed`.
+	pub fn optimizer<'a>(&self, problem: Problem<'a>, top: usize, min_distance: usize, swaps: usize, seed: u64)
+	-> Box<dyn Optimizer + 'a>
+	{
+		match *self {
+			Strategy::Annealing =>
+				Box::new(annealing::Annealing::new(problem, top, min_distance, swaps, seed)),
+			Strategy::Tabu { tenure, steps } =>
+				Box::new(tabu::Tabu::new(problem, top, min_distance, tenure, steps, seed)),
+			Strategy::Genetic { population, generations } =>
+				Box::new(genetic::Genetic::new(problem, top, min_distance, population, generations, swaps, seed)),
+			Strategy::Tempering { replicas, steps } =>
+				Box::new(tempering::Tempering::new(problem, top, min_distance, replicas, steps, swaps, seed)),
+		}
+	}
+
+	// The optimiser and its settings, for the metadata of the output.
+	pub fn metadata(&self)
+	-> Vec<(&'static str, Json)>
+	{
+		match *self {
+			Strategy::Annealing => vec![
+				("optimizer", Json::from("annealing")),
+				("schedule", Json::obj(vec![
+					("t0", Json::from(annealing::T0)),
+					("k", Json::from(annealing::K)),
+					("p0", Json::from(annealing::P0)),
+					("iterations", Json::from(annealing::N)),
+				])),
+			],
+			Strategy::Tabu { tenure, steps } => vec![
+				("optimizer", Json::from("tabu")),
+				("tenure", Json::from(tenure)),
+				("steps", Json::from(steps)),
+			],
+			Strategy::Genetic { population, generations } => vec![
+				("optimizer", Json::from("genetic")),
+				("population", Json::from(population)),
+				("generations", Json::from(generations)),
+			],
+			Strategy::Tempering { replicas, steps } => vec![
+				("optimizer", Json::from("tempering")),
+				("replicas", Json::from(replicas)),
+				("steps", Json::from(steps)),
+			],
+		}
+	}
+}
@@ -0,0 +1,350 @@
+//! Writing results as JSON or CSV for scripts and dashboards, rather than as
+//! text for people.
+//!
+//! JSON output is one document per line, each starting with the fields that
+//! describe the run (the command, corpus and so on). CSV output has a header
+//! row before the first row of data.
+
+use std::cell::Cell;
+use std::fmt;
+
+use layout;
+use layout::Layout;
+use layoutfile;
+use penalty::KeyPenaltyResult;
+
+// How many of each penalty's costliest n-grams are given for a layout.
+pub const TOP_KEYS: usize = 5;
+
+pub type Penalty = (f64, f64, Vec<KeyPenaltyResult>);
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum OutputFormat
+{
+	Text,
+	Json,
+	Csv,
+}
+
+// A JSON value to be written.
+#[derive(Clone)]
+pub enum Json
+{
+	Null,
+	Bool(bool),
+	Num(f64),
+	// Counts and seeds, which f64 can't hold exactly above 2^53.
+	Int(u64),
+	Str(String),
+	Arr(Vec<Json>),
+	Obj(Vec<(String, Json)>),
+}
+
+// A layout with its penalty, and the name it is reported under.
+pub struct Scored<'a>
+{
+	pub name:    String,
+	pub layout:  &'a Layout,
+	pub penalty: &'a Penalty,
+}
+
+pub struct Output
+{
+	pub format: OutputFormat,
+	metadata:   Vec<(String, Json)>,
+	csv_header: Cell<bool>,
+}
+
+pub fn parse_format(s: &str)
+-> Option<OutputFormat>
+{
+	match &s.to_lowercase()[..] {
+		"text" => Some(OutputFormat::Text),
+		"json" => Some(OutputFormat::Json),
+		"csv" => Some(OutputFormat::Csv),
+		_ => None,
+	}
+}
+
+impl Json
+{
+	pub fn obj(fields: Vec<(&str, Json)>)
+	-> Json
+	{
+		Json::Obj(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+	}
+}
+
+impl From<bool> for Json
+{
+	fn from(b: bool)
+	-> Json
+	{
+		Json::Bool(b)
+	}
+}
+
+impl From<f64> for Json
+{
+	fn from(n: f64)
+	-> Json
+	{
+		Json::Num(n)
+	}
+}
+
+impl From<usize> for Json
+{
+	fn from(n: usize)
+	-> Json
+	{
+		Json::Int(n as u64)
+	}
+}
+
+impl From<u64> for Json
+{
+	fn from(n: u64)
+	-> Json
+	{
+		Json::Int(n)
+	}
+}
+
+impl From<&str> for Json
+{
+	fn from(s: &str)
+	-> Json
+	{
+		Json::Str(s.to_string())
+	}
+}
+
+impl From<String> for Json
+{
+	fn from(s: String)
+	-> Json
+	{
+		Json::Str(s)
+	}
+}
+
+impl<T> From<Option<T>> for Json
+where T: Into<Json>
+{
+	fn from(o: Option<T>)
+	-> Json
+	{
+		match o {
+			Some(v) => v.into(),
+			None => Json::Null,
+		}
+	}
+}
+
+impl fmt::Display for Json
+{
+	fn fmt(&self, f: &mut fmt::Formatter)
+	-> fmt::Result
+	{
+		match *self {
+			Json::Null => write!(f, "null"),
+			Json::Bool(b) => write!(f, "{}", b),
+			// JSON has no infinities or NaN.
+			Json::Num(n) if !n.is_finite() => write!(f, "null"),
+			Json::Num(n) => write!(f, "{}", n),
+			Json::Int(n) => write!(f, "{}", n),
+			Json::Str(ref s) => write_string(f, s),
+			Json::Arr(ref items) => {
+				write!(f, "[")?;
+				for (i, item) in items.iter().enumerate() {
+					if i > 0 {
+						write!(f, ",")?;
+					}
+					write!(f, "{}", item)?;
+				}
+				write!(f, "]")
+			},
+			Json::Obj(ref fields) => {
+				write!(f, "{{")?;
+				for (i, (k, v)) in fields.iter().enumerate() {
+					if i > 0 {
+						write!(f, ",")?;
+					}
+					write_string(f, k)?;
+					write!(f, ":{}", v)?;
+				}
+				write!(f, "}}")
+			},
+		}
+	}
+}
+
+impl Output
+{
+	// `metadata` are the fields that every JSON document starts with.
+	pub fn new(format: OutputFormat, metadata: Vec<(&str, Json)>)
+	-> Output
+	{
+		Output {
+			format,
+			metadata: metadata.into_iter().map(|(k, v)| (k.to_string(), v)).collect(),
+			csv_header: Cell::new(false),
+		}
+	}
+
+	pub fn is_text(&self)
+	-> bool
+	{
+		self.format == OutputFormat::Text
+	}
+
+	// Prints the metadata and `fields` as one JSON document on one line.
+	pub fn print_json(&self, fields: Vec<(&str, Json)>)
+	{
+		let mut doc: Vec<(String, Json)> = self.metadata.iter()
+			.map(|(k, v)| (k.clone(), v.clone()))
+			.collect();
+		doc.extend(fields.into_iter().map(|(k, v)| (k.to_string(), v)));
+		println!("{}", Json::Obj(doc));
+	}
+
+	// Prints CSV rows, preceded by the header if none has been printed yet.
+	pub fn print_csv(&self, header: &[String], rows: &[Vec<String>])
+	{
+		if !self.csv_header.get() {
+			self.csv_header.set(true);
+			println!("{}", csv_row(header));
+		}
+		for row in rows {
+			println!("{}", csv_row(row));
+		}
+	}
+
+	// Prints scored layouts as JSON or CSV. `extra` are fields that describe
+	// where this set of layouts came from, such as the seed of the run; in
+	// CSV, they are the first columns of every row.
+	pub fn print_results(&self, extra: Vec<(&str, Json)>, results: &[Scored])
+	{
+		match self.format {
+			OutputFormat::Text => (),
+			OutputFormat::Json => {
+				let mut fields = extra;
+				fields.push(("layouts", Json::Arr(results.iter().map(result_json).collect())));
+				self.print_json(fields);
+			},
+			OutputFormat::Csv => {
+				let mut header: Vec<String> = extra.iter().map(|f| f.0.to_string()).collect();
+				header.extend(["name", "lower", "upper", "total", "scaled"].iter().map(|s| s.to_string()));
+				if let Some(first) = results.first() {
+					header.extend(first.penalty.2.iter().map(|p| p.name.to_string()));
+				}
+
+				let rows: Vec<Vec<String>> = results.iter().map(|r| {
+					let mut row: Vec<String> = extra.iter().map(|f| csv_value(&f.1)).collect();
+					row.push(r.name.clone());
+					row.push(layer_tokens(r.layout.lower()).join(" "));
+					row.push(layer_tokens(r.layout.upper()).join(" "));
+					row.push(r.penalty.0.to_string());
+					row.push(r.penalty.1.to_string());
+					row.extend(r.penalty.2.iter().map(|p| p.total.to_string()));
+					row
+				}).collect();
+				self.print_csv(&header, &rows);
+			},
+		}
+	}
+}
+
+// Both layers of a layout, each as rows of keys written as in layout files.
+pub fn layout_json(layout: &Layout)
+-> Json
+{
+	let layer = |keys: &[char; 34]| {
+		let tokens = layer_tokens(keys);
+		Json::Arr([0..11, 11..22, 22..32, 32..34].iter()
+			.map(|r| Json::Arr(tokens[r.clone()].iter().map(|t| Json::from(&t[..])).collect()))
+			.collect())
+	};
+	Json::obj(vec![
+		("lower", layer(layout.lower())),
+		("upper", layer(layout.upper())),
+	])
+}
+
+pub fn result_json(result: &Scored)
+-> Json
+{
+	let (total, scaled, ref penalties) = *result.penalty;
+	Json::obj(vec![
+		("name", Json::from(&result.name[..])),
+		("layout", layout_json(result.layout)),
+		("total", Json::from(total)),
+		("scaled", Json::from(scaled)),
+		("penalties", Json::Arr(penalties.iter().map(penalty_json).collect())),
+	])
+}
+
+fn penalty_json(penalty: &KeyPenaltyResult)
+-> Json
+{
+	let top_keys = penalty.top_keys(TOP_KEYS).into_iter()
+		.map(|(keys, cost)| Json::obj(vec![
+			("keys", Json::from(layout::key_labels(keys))),
+			("penalty", Json::from(cost)),
+		]))
+		.collect();
+	Json::obj(vec![
+		("name", Json::from(penalty.name)),
+		("total", Json::from(penalty.total)),
+		("top_keys", Json::Arr(top_keys)),
+	])
+}
+
+fn layer_tokens(keys: &[char; 34])
+-> Vec<String>
+{
+	keys.iter().map(|&kc| layoutfile::key_token(kc)).collect()
+}
+
+// A value as a CSV field: strings as they are, anything else as JSON.
+fn csv_value(value: &Json)
+-> String
+{
+	match *value {
+		Json::Str(ref s) => s.clone(),
+		Json::Null => String::new(),
+		ref v => v.to_string(),
+	}
+}
+
+pub fn csv_row(fields: &[String])
+-> String
+{
+	fields.iter()
+		.map(|f| if f.contains(&[',', '"', '\n', '\r'][..]) {
+			format!("\"{}\"", f.replace('"', "\"\""))
+		} else {
+			f.clone()
+		})
+		.collect::<Vec<String>>()
+		.join(",")
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str)
+-> fmt::Result
+{
+	write!(f, "\"")?;
+	for c in s.chars() {
+		match c {
+			'"' => write!(f, "\\\"")?,
+			'\\' => write!(f, "\\\\")?,
+			'\n' => write!(f, "\\n")?,
+			'\r' => write!(f, "\\r")?,
+			'\t' => write!(f, "\\t")?,
+			c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+			c => write!(f, "{}", c)?,
+		}
+	}
+	write!(f, "\"")
+}
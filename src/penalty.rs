@@ -1,11 +1,14 @@
-/// Methods for calculating the penalty of a keyboard layout given an input
-/// corpus string.
+//! Methods for calculating the penalty of a keyboard layout given an input
+//! corpus string.
 
 use std::vec::Vec;
-use std::ops::Range;
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::fmt;
+use std::mem;
+use std::sync::OnceLock;
 
+use layout;
 use layout::Layout;
 use layout::LayoutPosMap;
 use layout::KeyMap;
@@ -14,22 +17,86 @@ use layout::Finger;
 use layout::Row;
 use layout::KP_NONE;
 
-pub struct KeyPenalty<'a>
+pub struct KeyPenalty
 {
-	name:      &'a str,
+	name:      &'static str,
 }
 
 #[derive(Clone)]
-pub struct KeyPenaltyResult<'a>
+pub struct KeyPenaltyResult
 {
-	pub name:  &'a str,
+	pub name:  &'static str,
 	pub total:     f64,
-	pub high_keys: HashMap<&'a str, f64>,
+	pub high_keys: HashMap<String, f64>,
 }
 
-pub struct QuartadList<'a>(HashMap<&'a str, usize>);
+pub struct QuartadList(pub(crate) HashMap<String, usize>);
+
+// The penalty of each quartad on one layout, indexed by the positions of its
+// keys. Moving a few keys only changes the penalties of the quartads that
+// contain them, so the penalty of a nearby layout is found without scoring
+// the rest again.
+//
+// A rearrangement of keys is made of cycles (see `Layout::rearranged`), and a
+// quartad whose keys are all in one cycle changes as it does when that cycle
+// is moved alone. So the change that a rearrangement makes is the sum of the
+// changes of its cycles, which are shared by many rearrangements, corrected
+// for the few quartads with keys in more than one cycle.
+pub struct PenaltyIndex
+{
+	layout:      Layout,
+	// The penalty of one press of each sequence of positions; see `costs`.
+	costs:       &'static [f32],
+	// Each quartad's keys, last first, with NO_KEY for none.
+	keys:        Vec<[u8; 4]>,
+	counts:      Vec<f64>,
+	penalties:   Vec<f64>,
+	// The positions of each quartad's keys, as bits.
+	positions:   Vec<u64>,
+	// The quartads with a key at each position, and with keys at each pair
+	// of positions p < q, at p * 34 + q.
+	by_position: Vec<Vec<usize>>,
+	by_pair:     Vec<Vec<usize>>,
+	total:       f64,
+}
+
+// Positions are 0 to 33, and NO_POSITION stands for a key that isn't on the
+// layout; keys are ASCII, and NO_KEY stands for anything else.
+const NO_POSITION: usize = 34;
+const NO_KEY:      u8 = 128;
+
+static COSTS: OnceLock<Vec<f32>> = OnceLock::new();
+
+// Counts quartads incrementally, so that a corpus can be fed in chunks of any
+// size without holding all of it in memory. Runs of keys carry over between
+// chunks until a character outside the layout (or `end_run`) breaks them.
+pub struct QuartadCounter<'a>
+{
+	position_map: &'a LayoutPosMap,
+	window:           String,
+	quartads:         HashMap<String, usize>,
+	len:              usize,
+	dropped:          HashMap<char, usize>,
+}
 
-impl <'a> fmt::Display for KeyPenaltyResult<'a>
+impl KeyPenaltyResult
+{
+	// The `n` n-grams with the largest costs, largest first.
+	pub fn top_keys(&self, n: usize)
+	-> Vec<(&str, f64)>
+	{
+		let mut high_keys: Vec<(&str, f64)> = self.high_keys.iter().map(|x| (&x.0[..], *x.1)).collect();
+		high_keys.sort_by(|a, b|
+			match b.1.abs().partial_cmp(&a.1.abs()) {
+				Some(Ordering::Equal) | None => a.0.cmp(b.0),
+				Some(c) => c,
+			});
+		high_keys.truncate(n);
+		high_keys
+	}
+}
+
+impl fmt::Display for KeyPenaltyResult
 {
 	fn fmt (&self, f: &mut fmt::Formatter) -> fmt::Result {
 		write!(f, "{}: {}", self.name, self.total)
@@ -42,15 +109,15 @@ static BASE_PENALTY: KeyMap<f64> = KeyMap([
 	2.0, 2.0, 1.5, 1.5, 2.5,    2.5, 1.5, 1.5, 2.0, 2.0,
 	                    0.0,    0.0]);
 
-pub fn init<'a>()
--> Vec<KeyPenalty<'a>>
+pub fn init()
+-> Vec<KeyPenalty>
 {
-	let mut penalties = Vec::new();
-
-	// Base penalty.
-	penalties.push(KeyPenalty {
-		name: "base",
-	});
+	let mut penalties = vec![
+		// Base penalty.
+		KeyPenalty {
+			name: "base",
+		},
+	];
 
 	// Penalise 5 points for using the same finger twice on different keys.
 	// An extra 5 points for using the centre column.
@@ -125,42 +192,98 @@ pub fn init<'a>()
 	penalties
 }
 
-pub fn prepare_quartad_list<'a>(
-	string:       &'a str,
-	position_map: &'a LayoutPosMap)
--> QuartadList<'a>
+impl <'a> QuartadCounter<'a>
 {
-	let mut range: Range<usize> = 0..0;
-	let mut quartads: HashMap<&str, usize> = HashMap::new();
-	for (i, c) in string.chars().enumerate() {
-		match *position_map.get_key_position(c) {
-			Some(_) => {
-				range.end = i + 1;
-				if range.end > 3 && range.start < range.end - 4 {
-					range.start = range.end - 4;
-				}
-				let quartad = &string[range.clone()];
-				let entry = quartads.entry(quartad).or_insert(0);
-				*entry += 1;
-			},
-			None => {
-				range = (i + 1)..(i + 1);
-			}
+	pub fn new(position_map: &'a LayoutPosMap)
+	-> QuartadCounter<'a>
+	{
+		QuartadCounter {
+			position_map,
+			window: String::with_capacity(4),
+			quartads: HashMap::new(),
+			len: 0,
+			dropped: HashMap::new(),
+		}
+	}
+
+	// Characters that were skipped because they are not on the layout.
+	pub fn dropped(&self)
+	-> &HashMap<char, usize>
+	{
+		&self.dropped
+	}
+
+	pub fn push_str(&mut self, string: &str)
+	{
+		self.len += string.len();
+		for c in string.chars() {
+			self.push_char(c);
 		}
 	}
 
-	QuartadList(quartads)
+	// Counts a single keystroke, e.g. from a keystroke log.
+	pub fn push_key(&mut self, kc: char)
+	{
+		self.len += 1;
+		self.push_char(kc);
+	}
+
+	// Forget the current run of keys, e.g. between two files of a corpus.
+	pub fn end_run(&mut self)
+	{
+		self.window.clear();
+	}
+
+	// Returns the quartads and the length of the corpus in bytes.
+	pub fn finish(self)
+	-> (QuartadList, usize)
+	{
+		(QuartadList(self.quartads), self.len)
+	}
+
+	fn push_char(&mut self, c: char)
+	{
+		if self.position_map.get_key_position(c).is_none() {
+			*self.dropped.entry(c).or_insert(0) += 1;
+			self.window.clear();
+			return;
+		}
+
+		// Position maps only hold ASCII keys, so every character that gets
+		// this far is one byte long and `len` counts characters.
+		if self.window.len() == 4 {
+			self.window.remove(0);
+		}
+		self.window.push(c);
+
+		// Only allocate a key the first time we see a quartad.
+		if let Some(count) = self.quartads.get_mut(&self.window) {
+			*count += 1;
+			return;
+		}
+		self.quartads.insert(self.window.clone(), 1);
+	}
+}
+
+// The quartads of `s`, restricted to the keys of `position_map`, and its
+// length in bytes.
+pub fn quartads_from_str(s: &str, position_map: &LayoutPosMap)
+-> (QuartadList, usize)
+{
+	let mut counter = QuartadCounter::new(position_map);
+	counter.push_str(s);
+	counter.finish()
 }
 
-pub fn calculate_penalty<'a>(
-	quartads:  &   QuartadList<'a>,
+pub fn calculate_penalty(
+	quartads:  &QuartadList,
 	len:           usize,
-	layout:    &   Layout,
-	penalties: &'a Vec<KeyPenalty>,
+	layout:    &Layout,
+	penalties: &[KeyPenalty],
 	detailed:      bool)
--> (f64, f64, Vec<KeyPenaltyResult<'a>>)
+-> (f64, f64, Vec<KeyPenaltyResult>)
 {
-	let QuartadList(ref quartads) = *quartads;
+	let QuartadList(quartads) = quartads;
 	let mut result: Vec<KeyPenaltyResult> = Vec::new();
 	let mut total = 0.0;
 
@@ -182,24 +305,305 @@ pub fn calculate_penalty<'a>(
 	(total, total / (len as f64), result)
 }
 
-fn penalty_for_quartad<'a, 'b>(
-	string:       &'a str,
+// How often each position of the layout is pressed.
+pub fn key_counts(quartads: &QuartadList, layout: &Layout)
+-> [f64; 34]
+{
+	let QuartadList(quartads) = quartads;
+	let position_map = layout.get_position_map();
+	let mut counts = [0.0; 34];
+	for (string, count) in quartads {
+		let last = string.chars().next_back();
+		if let Some(Some(ref kp)) = last.map(|c| position_map.get_key_position(c)) {
+			counts[kp.pos] += *count as f64;
+		}
+	}
+	counts
+}
+
+// The penalty of each position of the layout: every quartad's penalty goes to
+// the key that ends it.
+pub fn key_penalties(quartads: &QuartadList, layout: &Layout)
+-> [f64; 34]
+{
+	let QuartadList(quartads) = quartads;
+	let position_map = layout.get_position_map();
+	let mut penalties = [0.0; 34];
+	for (string, count) in quartads {
+		let last = string.chars().next_back();
+		if let Some(Some(ref kp)) = last.map(|c| position_map.get_key_position(c)) {
+			penalties[kp.pos] += penalty_for_quartad(string, *count, &position_map, &mut [], false);
+		}
+	}
+	penalties
+}
+
+impl PenaltyIndex
+{
+	pub fn new(quartads: &QuartadList, layout: &Layout)
+	-> PenaltyIndex
+	{
+		let QuartadList(quartads) = quartads;
+		let mut index = PenaltyIndex {
+			layout:      layout.clone(),
+			costs:       COSTS.get_or_init(costs),
+			keys:        Vec::with_capacity(quartads.len()),
+			counts:      Vec::with_capacity(quartads.len()),
+			penalties:   Vec::with_capacity(quartads.len()),
+			positions:   Vec::with_capacity(quartads.len()),
+			by_position: vec![Vec::new(); 34],
+			by_pair:     vec![Vec::new(); 34 * 34],
+			total:       0.0,
+		};
+		let position_of = positions_of(layout);
+		for (i, (string, count)) in quartads.iter().enumerate() {
+			let mut keys = [NO_KEY; 4];
+			for (key, c) in keys.iter_mut().zip(string.chars().rev()) {
+				if c < (128 as char) {
+					*key = c as u8;
+				}
+			}
+			let mut positions = 0u64;
+			for &key in keys.iter() {
+				if position_of[key as usize] != NO_POSITION {
+					positions |= 1 << position_of[key as usize];
+				}
+			}
+			let on_layout: Vec<usize> = (0..34).filter(|pos| positions & (1 << pos) != 0).collect();
+			for (j, &p) in on_layout.iter().enumerate() {
+				index.by_position[p].push(i);
+				for &q in on_layout[(j + 1)..].iter() {
+					index.by_pair[p * 34 + q].push(i);
+				}
+			}
+			index.keys.push(keys);
+			index.counts.push(*count as f64);
+			index.positions.push(positions);
+			let penalty = index.penalty(i, &position_of);
+			index.penalties.push(penalty);
+			index.total += penalty;
+		}
+		index
+	}
+
+	// The total penalty of the indexed layout.
+	pub fn total(&self)
+	-> f64
+	{
+		self.total
+	}
+
+	// The change in the total penalty from moving the keys round `cycle`.
+	pub fn cycle_change(&self, cycle: &[usize])
+	-> f64
+	{
+		let position_of = positions_of(&self.layout.rearranged(&[cycle]));
+		let moved = bits(cycle);
+		let mut change = 0.0;
+		for &pos in cycle {
+			for &i in self.by_position[pos].iter() {
+				// A quartad with several moved keys is scored only once.
+				if (self.positions[i] & moved).trailing_zeros() as usize == pos {
+					change += self.penalty(i, &position_of) - self.penalties[i];
+				}
+			}
+		}
+		change
+	}
+
+	// The change in the total penalty from moving the keys round all of
+	// `cycles` at once, given the change that each makes alone.
+	pub fn change<C>(&self, cycles: &[C], changes: &[f64])
+	-> f64
+	where C: AsRef<[usize]>
+	{
+		let mut change: f64 = changes.iter().sum();
+		if cycles.len() < 2 {
+			return change;
+		}
+
+		let cycle_bits: Vec<u64> = cycles.iter().map(|c| bits(c.as_ref())).collect();
+		let mut maps: Option<([usize; 129], Vec<[usize; 129]>)> = None;
+		for (i, a) in cycles.iter().enumerate() {
+			for b in cycles.iter().skip(i + 1) {
+				for &p in a.as_ref() {
+					for &q in b.as_ref() {
+						let pair = if p < q { (p, q) } else { (q, p) };
+						for &k in self.by_pair[pair.0 * 34 + pair.1].iter() {
+							// A quartad with keys in several cycles is scored
+							// only for the first two keys in different ones.
+							if first_cross_pair(self.positions[k], &cycle_bits) != pair {
+								continue;
+							}
+							let (ref after, ref alone) = *maps.get_or_insert_with(|| (
+								positions_of(&self.layout.rearranged(cycles)),
+								cycles.iter().map(|c| positions_of(&self.layout.rearranged(&[c]))).collect(),
+							));
+							// Take back what each cycle's change counted for
+							// this quartad, and count what it really is.
+							change += self.penalty(k, after) - self.penalties[k];
+							for (c, position_of) in alone.iter().enumerate() {
+								if self.positions[k] & cycle_bits[c] != 0 {
+									change -= self.penalty(k, position_of) - self.penalties[k];
+								}
+							}
+						}
+					}
+				}
+			}
+		}
+		change
+	}
+
+	// Moves the keys round `cycle`, so that the index is of the rearranged
+	// layout, rescoring only the quartads with a key in the cycle.
+	pub fn apply(&mut self, cycle: &[usize])
+	{
+		// Where the key at each position goes.
+		let mut moved_to: Vec<usize> = (0..34).collect();
+		for (n, &pos) in cycle.iter().enumerate() {
+			moved_to[pos] = cycle[(n + 1) % cycle.len()];
+		}
+		self.layout = self.layout.rearranged(&[cycle]);
+		let position_of = positions_of(&self.layout);
+
+		let moved = bits(cycle);
+		let mut touched = Vec::new();
+		for &pos in cycle {
+			for &i in self.by_position[pos].iter() {
+				// A quartad with several moved keys is rescored only once.
+				if (self.positions[i] & moved).trailing_zeros() as usize == pos {
+					touched.push(i);
+				}
+			}
+		}
+		for i in touched {
+			self.positions[i] = (0..34)
+				.filter(|&pos| self.positions[i] & (1 << pos) != 0)
+				.fold(0, |bits, pos| bits | 1 << moved_to[pos]);
+			let penalty = self.penalty(i, &position_of);
+			self.total += penalty - self.penalties[i];
+			self.penalties[i] = penalty;
+		}
+
+		// The quartads listed at each position, and each pair of positions,
+		// move with the keys.
+		let by_position: Vec<(usize, Vec<usize>)> = cycle.iter()
+			.map(|&pos| (moved_to[pos], mem::take(&mut self.by_position[pos])))
+			.collect();
+		for (pos, quartads) in by_position {
+			self.by_position[pos] = quartads;
+		}
+		let mut by_pair = Vec::new();
+		for p in 0..34 {
+			for q in (p + 1)..34 {
+				if moved & (1 << p | 1 << q) != 0 {
+					let (a, b) = (moved_to[p], moved_to[q]);
+					let pair = if a < b { a * 34 + b } else { b * 34 + a };
+					by_pair.push((pair, mem::take(&mut self.by_pair[p * 34 + q])));
+				}
+			}
+		}
+		for (pair, quartads) in by_pair {
+			self.by_pair[pair] = quartads;
+		}
+	}
+
+	// The penalty of quartad `i` on the layout with the positions `position_of`.
+	fn penalty(&self, i: usize, position_of: &[usize; 129])
+	-> f64
+	{
+		let keys = &self.keys[i];
+		let at = |n: usize| position_of[keys[n] as usize];
+		if at(0) == NO_POSITION {
+			return 0.0;
+		}
+		self.counts[i] * f64::from(self.costs[cost_index(at(0), at(1), at(2), at(3))])
+	}
+}
+
+// The position of each key of `layout`, as in `LayoutPosMap`.
+fn positions_of(layout: &Layout)
+-> [usize; 129]
+{
+	let position_map = layout.get_position_map();
+	let mut positions = [NO_POSITION; 129];
+	for (kc, pos) in positions.iter_mut().enumerate().take(128) {
+		if let Some(ref kp) = *position_map.get_key_position(kc as u8 as char) {
+			*pos = kp.pos;
+		}
+	}
+	positions
+}
+
+// The penalty of one press of the last key of every sequence of up to four
+// positions. It only depends on the positions, so quartads can be scored by
+// looking it up. Penalties are multiples of 1/8, which f32 holds exactly in
+// half the space of f64.
+fn costs()
+-> Vec<f32>
+{
+	let presses: Vec<Option<KeyPress>> = (0..34)
+		.map(|pos| Some(layout::key_press('\0', pos)))
+		.chain(Some(None))
+		.collect();
+	let mut costs = vec![0.0; 35 * 35 * 35 * 35];
+	for (p0, curr) in presses.iter().enumerate().take(34) {
+		let curr = curr.as_ref().unwrap();
+		for p1 in 0..35 {
+			for p2 in 0..35 {
+				for p3 in 0..35 {
+					costs[cost_index(p0, p1, p2, p3)] =
+						penalize("\0\0\0\0", 1, curr, &presses[p1], &presses[p2], &presses[p3], &mut [], false) as f32;
+				}
+			}
+		}
+	}
+	costs
+}
+
+// Where to find the cost of pressing `p0` after `p1`, `p2` and `p3`.
+fn cost_index(p0: usize, p1: usize, p2: usize, p3: usize)
+-> usize
+{
+	((p3 * 35 + p2) * 35 + p1) * 35 + p0
+}
+
+fn bits(positions: &[usize])
+-> u64
+{
+	positions.iter().fold(0, |bits, &pos| bits | 1 << pos)
+}
+
+// The lowest two of the positions `keys` that are in different cycles.
+fn first_cross_pair(keys: u64, cycle_bits: &[u64])
+-> (usize, usize)
+{
+	let moved = cycle_bits.iter().fold(0, |all, b| all | b);
+	let first = (keys & moved).trailing_zeros();
+	let own = cycle_bits.iter().find(|&&b| b & (1 << first) != 0).cloned().unwrap_or(0);
+	let second = (keys & moved & !own).trailing_zeros();
+	(first as usize, second as usize)
+}
+
+fn penalty_for_quartad(
+	string:       &str,
 	count:            usize,
-	position_map: &'b LayoutPosMap,
-	result:       &'b mut Vec<KeyPenaltyResult<'a>>,
+	position_map: &LayoutPosMap,
+	result:       &mut [KeyPenaltyResult],
 	detailed:         bool)
 -> f64
 {
-	let mut chars = string.chars().into_iter().rev();
+	let mut chars = string.chars().rev();
 	let opt_curr = chars.next();
 	let opt_old1 = chars.next();
 	let opt_old2 = chars.next();
 	let opt_old3 = chars.next();
 
 	let curr = match opt_curr {
-		Some(c) => match position_map.get_key_position(c) {
-			&Some(ref kp) => kp,
-			&None => { return 0.0 }
+		Some(c) => match *position_map.get_key_position(c) {
+			Some(ref kp) => kp,
+			None => { return 0.0 }
 		},
 		None => panic!("unreachable")
 	};
@@ -216,17 +620,18 @@ fn penalty_for_quartad<'a, 'b>(
 		None => &KP_NONE
 	};
 
-	penalize(string, count, &curr, old1, old2, old3, result, detailed)
+	penalize(string, count, curr, old1, old2, old3, result, detailed)
 }
 
-fn penalize<'a, 'b>(
-	string: &'a     str,
+#[allow(clippy::too_many_arguments)]
+fn penalize(
+	string: &       str,
 	count:          usize,
 	curr:   &              KeyPress,
 	old1:   &       Option<KeyPress>,
 	old2:   &       Option<KeyPress>,
 	old3:   &       Option<KeyPress>,
-	result: &'b mut Vec<KeyPenaltyResult<'a>>,
+	result: &   mut [KeyPenaltyResult],
 	detailed:       bool)
 -> f64
 {
@@ -240,7 +645,7 @@ fn penalize<'a, 'b>(
 	// 0: Base penalty.
 	let base = BASE_PENALTY.0[curr.pos] * count;
 	if detailed {
-		*result[0].high_keys.entry(slice1).or_insert(0.0) += base;
+		*result[0].high_keys.entry(slice1.to_string()).or_insert(0.0) += base;
 		result[0].total += base;
 	}
 	total += base;
@@ -253,6 +658,7 @@ fn penalize<'a, 'b>(
 
 	if curr.hand == old1.hand {
 		let slice2 = &string[(len - 2)..len];
+		let long_jump = is_long_jump(curr.row, old1.row);
 
 		// 1: Same finger.
 		if curr.finger == old1.finger && curr.pos != old1.pos {
@@ -260,87 +666,80 @@ fn penalize<'a, 'b>(
 			                  + if old1.center { 5.0 } else { 0.0 };
 			let penalty = penalty * count;
 			if detailed {
-				*result[1].high_keys.entry(slice2).or_insert(0.0) += penalty;
+				*result[1].high_keys.entry(slice2.to_string()).or_insert(0.0) += penalty;
 				result[1].total += penalty;
 			}
 			total += penalty;
 		}
 
 		// 2: Long jump hand.
-		if curr.row == Row::Top && old1.row == Row::Bottom ||
-		   curr.row == Row::Bottom && old1.row == Row::Top {
+		if long_jump {
 			let penalty = count;
 			if detailed {
-				*result[2].high_keys.entry(slice2).or_insert(0.0) += penalty;
+				*result[2].high_keys.entry(slice2.to_string()).or_insert(0.0) += penalty;
 				result[2].total += penalty;
 			}
 			total += penalty;
 		}
 
 		// 3: Long jump.
-		if curr.hand == old1.hand && curr.finger == old1.finger {
-			if curr.row == Row::Top && old1.row == Row::Bottom ||
-			   curr.row == Row::Bottom && old1.row == Row::Top {
-				let penalty = 10.0 * count;
-				if detailed {
-					*result[3].high_keys.entry(slice2).or_insert(0.0) += penalty;
-					result[3].total += penalty;
-				}
-				total += penalty;
+		if long_jump && curr.finger == old1.finger {
+			let penalty = 10.0 * count;
+			if detailed {
+				*result[3].high_keys.entry(slice2.to_string()).or_insert(0.0) += penalty;
+				result[3].total += penalty;
 			}
+			total += penalty;
 		}
 
-		// 4: Long jump consecutive.
-		if curr.row == Row::Top && old1.row == Row::Bottom ||
-		   curr.row == Row::Bottom && old1.row == Row::Top {
-			if curr.finger == Finger::Ring   && old1.finger == Finger::Pinky  ||
-			   curr.finger == Finger::Pinky  && old1.finger == Finger::Ring   ||
-			   curr.finger == Finger::Middle && old1.finger == Finger::Ring   ||
-			   curr.finger == Finger::Ring   && old1.finger == Finger::Middle ||
-			  (curr.finger == Finger::Index  && (old1.finger == Finger::Middle ||
-			                                     old1.finger == Finger::Ring) &&
-			   curr.row == Row::Top && old1.row == Row::Bottom) {
-				let penalty = 5.0 * count;
-				if detailed {
-					*result[4].high_keys.entry(slice2).or_insert(0.0) += penalty;
-					result[4].total += penalty;
-				}
-				total += penalty;
+		// 4: Long jump consecutive: between neighbouring fingers other than
+		// the index, or up from the middle or ring finger to the index.
+		let consecutive = match (curr.finger, old1.finger) {
+			(Finger::Ring, Finger::Pinky) | (Finger::Pinky, Finger::Ring) |
+			(Finger::Middle, Finger::Ring) | (Finger::Ring, Finger::Middle) => true,
+			(Finger::Index, Finger::Middle) | (Finger::Index, Finger::Ring) => curr.row == Row::Top,
+			_ => false,
+		};
+		if long_jump && consecutive {
+			let penalty = 5.0 * count;
+			if detailed {
+				*result[4].high_keys.entry(slice2.to_string()).or_insert(0.0) += penalty;
+				result[4].total += penalty;
 			}
+			total += penalty;
 		}
 
-		// 5: Pinky/ring twist.
-		if (curr.finger == Finger::Ring && old1.finger == Finger::Pinky &&
-		    (curr.row == Row::Home && old1.row == Row::Top ||
-		     curr.row == Row::Bottom && old1.row == Row::Top)) ||
-		   (curr.finger == Finger::Pinky && old1.finger == Finger::Ring &&
-		    (curr.row == Row::Top && old1.row == Row::Home ||
-		     curr.row == Row::Top && old1.row == Row::Bottom)) {
+		// 5: Pinky/ring twist: the pinky on the top row next to the ring
+		// finger below it, in either order.
+		let twist = matches!((curr.finger, old1.finger, curr.row, old1.row),
+			(Finger::Ring, Finger::Pinky, Row::Home, Row::Top) |
+			(Finger::Ring, Finger::Pinky, Row::Bottom, Row::Top) |
+			(Finger::Pinky, Finger::Ring, Row::Top, Row::Home) |
+			(Finger::Pinky, Finger::Ring, Row::Top, Row::Bottom));
+		if twist {
 			let penalty = 10.0 * count;
 			if detailed {
-				*result[5].high_keys.entry(slice2).or_insert(0.0) += penalty;
+				*result[5].high_keys.entry(slice2.to_string()).or_insert(0.0) += penalty;
 				result[5].total += penalty;
 			}
 			total += penalty;
 		}
 
 		// 9: Roll out.
-		if curr.hand == old1.hand &&
-		   old1.finger != Finger::Thumb &&
-		   is_roll_out(curr.finger, old1.finger) {
+		if old1.finger != Finger::Thumb && is_roll_out(curr.finger, old1.finger) {
 			let penalty = 0.125 * count;
 			if detailed {
-				*result[9].high_keys.entry(slice2).or_insert(0.0) += penalty;
+				*result[9].high_keys.entry(slice2.to_string()).or_insert(0.0) += penalty;
 				result[9].total += penalty;
 			}
 			total += penalty;
 		}
 
 		// 10: Roll in.
-		if curr.hand == old1.hand && is_roll_in(curr.finger, old1.finger) {
+		if is_roll_in(curr.finger, old1.finger) {
 			let penalty = -0.125 * count;
 			if detailed {
-				*result[10].high_keys.entry(slice2).or_insert(0.0) += penalty;
+				*result[10].high_keys.entry(slice2.to_string()).or_insert(0.0) += penalty;
 				result[10].total += penalty;
 			}
 			total += penalty;
@@ -355,26 +754,28 @@ fn penalize<'a, 'b>(
 
 	if curr.hand == old1.hand && old1.hand == old2.hand {
 		// 6: Roll reversal.
-		if (curr.finger == Finger::Middle && old1.finger == Finger::Pinky && old2.finger == Finger::Ring) ||
-		    curr.finger == Finger::Ring && old1.finger == Finger::Pinky && old2.finger == Finger::Middle {
+		let reversal = matches!((curr.finger, old1.finger, old2.finger),
+			(Finger::Middle, Finger::Pinky, Finger::Ring) |
+			(Finger::Ring, Finger::Pinky, Finger::Middle));
+		if reversal {
 			let slice3 = &string[(len - 3)..len];
 			let penalty = 20.0 * count;
 			if detailed {
-				*result[6].high_keys.entry(slice3).or_insert(0.0) += penalty;
+				*result[6].high_keys.entry(slice3.to_string()).or_insert(0.0) += penalty;
 				result[6].total += penalty;
 			}
 			total += penalty;
 		}
 
-		// 12: Twist.
-		if ((curr.row == Row::Top && old1.row == Row::Home && old2.row == Row::Bottom) ||
-		    (curr.row == Row::Bottom && old1.row == Row::Home && old2.row == Row::Top)) &&
-		   ((is_roll_out(curr.finger, old1.finger) && is_roll_out(old1.finger, old2.finger)) ||
-		   	(is_roll_in(curr.finger, old1.finger) && is_roll_in(old1.finger, old2.finger))) {
+		// 12: Twist: across all three rows while rolling one way.
+		let across = old1.row == Row::Home && is_long_jump(curr.row, old2.row);
+		let roll = is_roll_out(curr.finger, old1.finger) && is_roll_out(old1.finger, old2.finger) ||
+		           is_roll_in(curr.finger, old1.finger) && is_roll_in(old1.finger, old2.finger);
+		if across && roll {
 			let slice3 = &string[(len - 3)..len];
 			let penalty = 10.0 * count;
 			if detailed {
-				*result[12].high_keys.entry(slice3).or_insert(0.0) += penalty;
+				*result[12].high_keys.entry(slice3.to_string()).or_insert(0.0) += penalty;
 				result[12].total += penalty;
 			}
 			total += penalty;
@@ -382,17 +783,14 @@ fn penalize<'a, 'b>(
 	}
 
 	// 11: Long jump sandwich.
-	if curr.hand == old2.hand && curr.finger == old2.finger {
-		if curr.row == Row::Top && old2.row == Row::Bottom ||
-		   curr.row == Row::Bottom && old2.row == Row::Top {
-			let penalty = 3.0 * count;
-			if detailed {
-				let slice3 = &string[(len - 3)..len];
-				*result[11].high_keys.entry(slice3).or_insert(0.0) += penalty;
-				result[11].total += penalty;
-			}
-			total += penalty;
+	if curr.hand == old2.hand && curr.finger == old2.finger && is_long_jump(curr.row, old2.row) {
+		let penalty = 3.0 * count;
+		if detailed {
+			let slice3 = &string[(len - 3)..len];
+			*result[11].high_keys.entry(slice3.to_string()).or_insert(0.0) += penalty;
+			result[11].total += penalty;
 		}
+		total += penalty;
 	}
 
 	// Four key penalties.
@@ -406,7 +804,7 @@ fn penalize<'a, 'b>(
 		let slice4 = &string[(len - 4)..len];
 		let penalty = 0.5 * count;
 		if detailed {
-			*result[7].high_keys.entry(slice4).or_insert(0.0) += penalty;
+			*result[7].high_keys.entry(slice4.to_string()).or_insert(0.0) += penalty;
 			result[7].total += penalty;
 		}
 		total += penalty;
@@ -415,7 +813,7 @@ fn penalize<'a, 'b>(
 		let slice4 = &string[(len - 4)..len];
 		let penalty = 0.5 * count;
 		if detailed {
-			*result[8].high_keys.entry(slice4).or_insert(0.0) += penalty;
+			*result[8].high_keys.entry(slice4.to_string()).or_insert(0.0) += penalty;
 			result[8].total += penalty;
 		}
 		total += penalty;
@@ -424,6 +822,11 @@ fn penalize<'a, 'b>(
 	total
 }
 
+// Whether going between rows `a` and `b` jumps over the home row.
+fn is_long_jump(a: Row, b: Row) -> bool {
+	a == Row::Top && b == Row::Bottom || a == Row::Bottom && b == Row::Top
+}
+
 fn is_roll_out(curr: Finger, prev: Finger) -> bool {
 	match curr {
 		Finger::Thumb  => false,
@@ -443,3 +846,79 @@ fn is_roll_in(curr: Finger, prev: Finger) -> bool {
 		Finger::Pinky  => false,
 	}
 }
+
+#[cfg(test)]
+mod tests
+{
+	extern crate rand;
+
+	use super::*;
+
+	use self::rand::Rng;
+
+	use optimizer;
+
+	static TEXT: &str = "The quick brown fox jumps over the lazy dog; pack my box with five dozen \
+	                     liquor jugs. \"Sphinx of black quartz, judge my vow!\" - who'd've thought?";
+
+	fn quartads()
+	-> QuartadList
+	{
+		let position_map = layout::INIT_LAYOUT.get_position_map();
+		let mut counter = QuartadCounter::new(&position_map);
+		counter.push_str(TEXT);
+		counter.finish().0
+	}
+
+	#[test]
+	fn applying_a_cycle_matches_a_new_index()
+	{
+		let quartads = quartads();
+		let mut index = PenaltyIndex::new(&quartads, &layout::INIT_LAYOUT);
+		let mut layout = layout::INIT_LAYOUT.clone();
+		for cycle in [vec![3, 17], vec![0, 12, 33], vec![5, 6], vec![17, 3]].iter() {
+			index.apply(cycle);
+			layout = layout.rearranged(&[cycle]);
+			let new = PenaltyIndex::new(&quartads, &layout);
+			assert!((index.total() - new.total()).abs() < 1e-6);
+
+			let cycles = [[1, 20], [7, 30]];
+			let changes = [index.cycle_change(&cycles[0]), index.cycle_change(&cycles[1])];
+			let new_changes = [new.cycle_change(&cycles[0]), new.cycle_change(&cycles[1])];
+			assert!((changes[0] - new_changes[0]).abs() < 1e-6);
+			assert!((index.change(&cycles, &changes) - new.change(&cycles, &new_changes)).abs() < 1e-6);
+		}
+	}
+
+	#[test]
+	fn changes_add_up_to_the_penalty_of_the_rearranged_layout()
+	{
+		let quartads = quartads();
+		let penalties = init();
+		let index = PenaltyIndex::new(&quartads, &layout::INIT_LAYOUT);
+		let mut rng = optimizer::seeded_rng(1);
+		for n in 1..4 {
+			for _ in 0..50 {
+				// `n` disjoint cycles of two to four positions each.
+				let mut positions: Vec<usize> = (0..34).collect();
+				rng.shuffle(&mut positions);
+				let mut cycles: Vec<Vec<usize>> = Vec::new();
+				for _ in 0..n {
+					let len = 2 + rng.gen::<usize>() % 3;
+					let mut cycle: Vec<usize> = positions.drain(..len).collect();
+					cycle.sort_unstable();
+					cycles.push(cycle);
+				}
+				cycles.sort();
+
+				let changes: Vec<f64> = cycles.iter().map(|c| index.cycle_change(c)).collect();
+				let layout = layout::INIT_LAYOUT.rearranged(&cycles);
+				let expected = calculate_penalty(&quartads, 0, &layout, &penalties, false).0;
+				if n == 1 {
+					assert!((index.total() + changes[0] - expected).abs() < 1e-6, "{:?}", cycles);
+				}
+				assert!((index.total() + index.change(&cycles, &changes) - expected).abs() < 1e-6, "{:?}", cycles);
+			}
+		}
+	}
+}
@@ -0,0 +1,100 @@
+//! Renders a layout as a QMK `keymap.c`.
+//!
+//! The lower layer becomes the base layer. Where the upper layer doesn't hold
+//! the symbol that shift would produce on a US keyboard, a key override makes
+//! shift send the upper layer's symbol instead.
+
+use layout;
+use layout::Layout;
+use export;
+use export::ExportOptions;
+
+static KEYCODES: [(char, &str); 44] = [
+	('1', "KC_1"), ('2', "KC_2"), ('3', "KC_3"), ('4', "KC_4"), ('5', "KC_5"),
+	('6', "KC_6"), ('7', "KC_7"), ('8', "KC_8"), ('9', "KC_9"), ('0', "KC_0"),
+	('-', "KC_MINS"), ('=', "KC_EQL"), ('[', "KC_LBRC"), (']', "KC_RBRC"),
+	('\\', "KC_BSLS"), (';', "KC_SCLN"), ('\'', "KC_QUOT"), ('`', "KC_GRV"),
+	(',', "KC_COMM"), ('.', "KC_DOT"), ('/', "KC_SLSH"), (' ', "KC_SPC"),
+	('~', "KC_TILD"), ('!', "KC_EXLM"), ('@', "KC_AT"), ('#', "KC_HASH"),
+	('$', "KC_DLR"), ('%', "KC_PERC"), ('^', "KC_CIRC"), ('&', "KC_AMPR"),
+	('*', "KC_ASTR"), ('(', "KC_LPRN"), (')', "KC_RPRN"), ('_', "KC_UNDS"),
+	('+', "KC_PLUS"), ('{', "KC_LCBR"), ('}', "KC_RCBR"), ('|', "KC_PIPE"),
+	(':', "KC_COLN"), ('"', "KC_DQUO"), ('<', "KC_LABK"), ('>', "KC_RABK"),
+	('?', "KC_QUES"), ('\0', "KC_NO")];
+
+pub fn render(layout: &Layout, options: &ExportOptions)
+-> String
+{
+	let rows: Vec<Vec<String>> = export::physical_keys(layout.lower(), options).iter()
+		.map(|row| row.iter().map(|kc| keycode(kc.unwrap_or('\0'))).collect())
+		.collect();
+
+	let mut s = String::new();
+	s.push_str(&format!("// {} layout, generated by keygen.\n", options.name));
+	s.push_str(&export::diagram(layout, "// "));
+	s.push_str("\n#include QMK_KEYBOARD_H\n\n");
+	s.push_str("enum layers {\n    _BASE,\n};\n\n");
+	s.push_str("const uint16_t PROGMEM keymaps[][MATRIX_ROWS][MATRIX_COLS] = {\n");
+	s.push_str(&format!("    [_BASE] = {}(\n", options.layout_macro));
+	let width = rows.iter().flat_map(|r| r.iter()).map(|c| c.len()).max().unwrap_or(0) + 1;
+	for (i, row) in rows.iter().enumerate() {
+		let last_row = i + 1 == rows.len();
+		let cells: Vec<String> = row.iter().enumerate().map(|(j, code)| {
+			if last_row && j + 1 == row.len() {
+				code.clone()
+			} else {
+				format!("{:<w$}", format!("{},", code), w = width)
+			}
+		}).collect();
+		s.push_str(&format!("        {}\n", cells.join(" ").trim_end()));
+	}
+	s.push_str("    ),\n};\n");
+
+	let overrides = shift_overrides(layout, options);
+	if !overrides.is_empty() {
+		let id = export::identifier(&options.name);
+		s.push_str("\n// Shifted symbols that differ from a US keyboard.\n");
+		s.push_str("// Requires KEY_OVERRIDE_ENABLE = yes in rules.mk.\n");
+		for (i, (trigger, replacement)) in overrides.iter().enumerate() {
+			s.push_str(&format!("const key_override_t {}_shift_{} = ko_make_basic(MOD_MASK_SHIFT, {}, {});\n",
+				id, i, trigger, replacement));
+		}
+		s.push_str("\nconst key_override_t *key_overrides[] = {\n");
+		for i in 0..overrides.len() {
+			s.push_str(&format!("    &{}_shift_{},\n", id, i));
+		}
+		s.push_str("};\n");
+	}
+
+	s
+}
+
+// The QMK keycode that types `kc`.
+pub fn keycode(kc: char)
+-> String
+{
+	if kc.is_ascii_lowercase() {
+		return format!("KC_{}", kc.to_ascii_uppercase());
+	}
+	if kc.is_ascii_uppercase() {
+		return format!("S(KC_{})", kc);
+	}
+	if let Some(code) = KEYCODES.iter().find(|k| k.0 == kc) {
+		return code.1.to_string();
+	}
+	if let Some(codes) = layout::key_codes(kc) {
+		return codes.qmk.to_string();
+	}
+
+	// Needs UNICODE_ENABLE = yes.
+	format!("UC(0x{:04X})", kc as u32)
+}
+
+// Key overrides for positions whose upper key isn't what shift would type.
+fn shift_overrides(layout: &Layout, options: &ExportOptions)
+-> Vec<(String, String)>
+{
+	export::shift_overrides(layout, &export::matrix_positions(options)).iter()
+		.map(|&pos| (keycode(layout.lower()[pos]), keycode(layout.upper()[pos])))
+		.collect()
+}
@@ -0,0 +1,132 @@
+//! Finding layouts by name: the built-in reference layouts, and the layout
+//! files in the user's layouts directory.
+
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::path::PathBuf;
+
+use error::Error;
+use error::Result;
+use import;
+use layout;
+use layout::Layout;
+use layout::LayoutShuffleMask;
+
+pub static BUILTIN_LAYOUTS: [(&str, &Layout); 10] = [
+	("qwerty",   &layout::QWERTY_LAYOUT),
+	("dvorak",   &layout::DVORAK_LAYOUT),
+	("colemak",  &layout::COLEMAK_LAYOUT),
+	("qgmlwy",   &layout::QGMLWY_LAYOUT),
+	("workman",  &layout::WORKMAN_LAYOUT),
+	("maltron",  &layout::MALTRON_LAYOUT),
+	("mtgap",    &layout::MTGAP_LAYOUT),
+	("capewell", &layout::CAPEWELL_LAYOUT),
+	("arensito", &layout::ARENSITO_LAYOUT),
+	("init",     &layout::INIT_LAYOUT),
+];
+
+pub struct NamedLayout
+{
+	pub name:   String,
+	pub layout: Layout,
+	pub mask:   LayoutShuffleMask,
+}
+
+// Default layouts directory: $XDG_CONFIG_HOME/keygen/layouts or
+// ~/.config/keygen/layouts.
+pub fn default_dir()
+-> PathBuf
+{
+	if let Some(dir) = env::var_os("XDG_CONFIG_HOME") {
+		if !dir.is_empty() {
+			return PathBuf::from(dir).join("keygen").join("layouts");
+		}
+	}
+	match env::var_os("HOME") {
+		Some(home) => PathBuf::from(home).join(".config").join("keygen").join("layouts"),
+		None => PathBuf::from("layouts"),
+	}
+}
+
+// Finds a layout given as a path to a file, the name of a built-in layout, or
+// the name of a file in `dir` with or without its extension, in that order.
+pub fn find(spec: &str, dir: &Path)
+-> Result<NamedLayout>
+{
+	let path = Path::new(spec);
+	if path.is_file() {
+		return read_file(path);
+	}
+
+	let lower = spec.to_lowercase();
+	if let Some(&(name, layout)) = BUILTIN_LAYOUTS.iter().find(|l| l.0 == lower) {
+		return Ok(NamedLayout {
+			name: name.to_string(),
+			layout: layout.clone(),
+			mask: layout::LAYOUT_MASK.clone(),
+		});
+	}
+
+	if let Some(path) = user_layouts(dir).into_iter().find(|p| name_of(p) == spec) {
+		return read_file(&path);
+	}
+
+	Err(Error::config(format!("no layout file or layout named {:?}; known layouts are {}", spec, names(dir).join(", "))))
+}
+
+// The names of the built-in layouts.
+pub fn builtin_names()
+-> Vec<String>
+{
+	BUILTIN_LAYOUTS.iter().map(|l| l.0.to_string()).collect()
+}
+
+// The names of all layouts that `find` knows by name.
+pub fn names(dir: &Path)
+-> Vec<String>
+{
+	let mut names = builtin_names();
+	names.extend(user_layouts(dir).iter().map(|p| name_of(p)));
+	names
+}
+
+pub fn read_file(path: &Path)
+-> Result<NamedLayout>
+{
+	let mut s = String::new();
+	File::open(path)
+		.and_then(|mut f| f.read_to_string(&mut s))
+		.map_err(|e| Error::io(e, path))?;
+	let (layout, mask) = import::parse(&s).map_err(|e| e.in_file(path))?;
+	Ok(NamedLayout {
+		name: name_of(path),
+		layout,
+		mask,
+	})
+}
+
+// The files in `dir`, sorted; none if it doesn't exist.
+fn user_layouts(dir: &Path)
+-> Vec<PathBuf>
+{
+	let mut paths: Vec<PathBuf> = match fs::read_dir(dir) {
+		Ok(entries) => entries
+			.filter_map(|e| e.ok().map(|e| e.path()))
+			.filter(|p| p.is_file() && !name_of(p).starts_with('.'))
+			.collect(),
+		Err(_) => Vec::new(),
+	};
+	paths.sort();
+	paths
+}
+
+fn name_of(path: &Path)
+-> String
+{
+	path.file_stem()
+		.map(|s| s.to_string_lossy().into_owned())
+		.unwrap_or_else(|| path.display().to_string())
+}
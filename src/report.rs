@@ -0,0 +1,202 @@
+//! A self-contained HTML report on a layout, to share in design reviews.
+//!
+//! The report has no external resources: the layout diagram is inline SVG
+//! and the charts are bars drawn with CSS.
+
+use std::cmp::Ordering;
+
+use layout;
+use layout::Finger;
+use layout::Hand;
+use layout::Layout;
+use layoutfile;
+use output::Penalty;
+use penalty;
+use penalty::QuartadList;
+use registry;
+use registry::NamedLayout;
+use svg;
+use xml;
+
+static FINGERS: [(Hand, Finger, &str); 10] = [
+	(Hand::Left,  Finger::Pinky,  "left pinky"),
+	(Hand::Left,  Finger::Ring,   "left ring"),
+	(Hand::Left,  Finger::Middle, "left middle"),
+	(Hand::Left,  Finger::Index,  "left index"),
+	(Hand::Left,  Finger::Thumb,  "left thumb"),
+	(Hand::Right, Finger::Thumb,  "right thumb"),
+	(Hand::Right, Finger::Index,  "right index"),
+	(Hand::Right, Finger::Middle, "right middle"),
+	(Hand::Right, Finger::Ring,   "right ring"),
+	(Hand::Right, Finger::Pinky,  "right pinky"),
+];
+
+static STYLE: &str = "
+body { font-family: sans-serif; margin: 2em auto; max-width: 60em; color: #222; }
+h1 { margin-bottom: 0.2em; }
+h2 { margin-top: 1.6em; border-bottom: 1px solid #ccc; }
+table { border-collapse: collapse; margin: 0.5em 0; }
+th, td { padding: 0.2em 0.8em; text-align: right; }
+th:first-child, td:first-child { text-align: left; }
+tr:nth-child(even) { background: #f4f4f4; }
+tr.this { font-weight: bold; background: #fde0dd; }
+.bar { width: 20em; background: #eee; }
+.bar span { display: block; height: 1em; background: #d73027; }
+.bar span.reward { background: #1a9850; }
+.ngrams { display: flex; flex-wrap: wrap; gap: 0 2em; }
+.muted { color: #777; }
+code { white-space: pre; }
+";
+
+pub struct Report<'a>
+{
+	pub name:       &'a str,
+	pub corpus:     &'a str,
+	pub layout:     &'a Layout,
+	pub penalty:    &'a Penalty,
+	// How often each position of the layout is pressed.
+	pub counts:     [f64; 34],
+	// The layout, drawn as SVG.
+	pub diagram:    String,
+	// The names and penalties of the layouts to compare against.
+	pub references: Vec<(String, Penalty)>,
+	// How many of the worst n-grams of each penalty to list.
+	pub top:        usize,
+}
+
+// An HTML report on `l`, listing the `top` costliest n-grams of each penalty.
+pub fn report(quartads: &QuartadList, len: usize, l: &NamedLayout, corpus_filename: &str, top: usize)
+-> String
+{
+	let penalties = penalty::init();
+
+	let counts = penalty::key_counts(quartads, &l.layout);
+	let penalty = penalty::calculate_penalty(quartads, len, &l.layout, &penalties, true);
+	let references = registry::BUILTIN_LAYOUTS.iter()
+		.filter(|r| r.0 != l.name)
+		.map(|&(name, layout)| (name.to_string(), penalty::calculate_penalty(quartads, len, layout, &penalties, false)))
+		.collect();
+	let diagram = svg::render(&l.layout, &svg::HeatMap {
+		title: &l.name,
+		legend: "keystrokes",
+		values: counts,
+		arcs: Vec::new(),
+	});
+
+	render(&Report {
+		name: &l.name,
+		corpus: corpus_filename,
+		layout: &l.layout,
+		penalty: &penalty,
+		counts,
+		diagram,
+		references,
+		top,
+	})
+}
+
+pub fn render(report: &Report)
+-> String
+{
+	let (total, scaled, ref penalties) = *report.penalty;
+
+	let mut s = String::new();
+	s.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+	s.push_str(&format!("<title>{} - keygen report</title>\n", xml::escape(report.name)));
+	s.push_str(&format!("<style>{}</style>\n</head>\n<body>\n", STYLE));
+	s.push_str(&format!("<h1>{}</h1>\n", xml::escape(report.name)));
+	s.push_str(&format!("<p class=\"muted\">Corpus: {}</p>\n", xml::escape(report.corpus)));
+	s.push_str(&format!("<p>Total penalty <b>{:.1}</b>, scaled <b>{:.4}</b>.</p>\n", total, scaled));
+
+	s.push_str("<h2>Layout</h2>\n");
+	s.push_str(&report.diagram);
+	s.push_str(&format!("<details>\n<summary>Layout file</summary>\n<pre>{}</pre>\n</details>\n",
+		xml::escape(&layoutfile::render(report.layout, report.name))));
+
+	s.push_str("<h2>Penalties</h2>\n<table>\n<tr><th>rule</th><th>total</th><th>share</th><th></th></tr>\n");
+	let largest = penalties.iter().map(|p| p.total.abs()).fold(0.0, f64::max);
+	for p in penalties {
+		s.push_str(&format!("<tr><td>{}</td><td>{:.1}</td><td>{}</td><td>{}</td></tr>\n",
+			xml::escape(p.name), p.total, percent(p.total, total), bar(p.total, largest)));
+	}
+	s.push_str("</table>\n");
+
+	s.push_str("<h2>Finger and hand usage</h2>\n<table>\n<tr><th>finger</th><th>keystrokes</th><th>share</th><th></th></tr>\n");
+	let mut fingers = [0.0; 10];
+	let mut hands = [0.0; 2];
+	for pos in 0..34 {
+		let (hand, finger) = layout::finger_of(pos);
+		if let Some(i) = FINGERS.iter().position(|f| f.0 == hand && f.1 == finger) {
+			fingers[i] += report.counts[pos];
+		}
+		hands[if hand == Hand::Left { 0 } else { 1 }] += report.counts[pos];
+	}
+	let keystrokes: f64 = report.counts.iter().sum();
+	let busiest = fingers.iter().cloned().fold(0.0, f64::max);
+	for (i, f) in FINGERS.iter().enumerate() {
+		s.push_str(&format!("<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+			f.2, fingers[i], percent(fingers[i], keystrokes), bar(fingers[i], busiest)));
+	}
+	s.push_str("</table>\n<table>\n<tr><th>hand</th><th>keystrokes</th><th>share</th><th></th></tr>\n");
+	for (i, name) in ["left", "right"].iter().enumerate() {
+		s.push_str(&format!("<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+			name, hands[i], percent(hands[i], keystrokes), bar(hands[i], keystrokes)));
+	}
+	s.push_str("</table>\n");
+
+	s.push_str("<h2>Worst n-grams</h2>\n<div class=\"ngrams\">\n");
+	for p in penalties {
+		let top_keys = p.top_keys(report.top);
+		if top_keys.is_empty() {
+			continue;
+		}
+		s.push_str(&format!("<table>\n<tr><th>{}</th><th>penalty</th></tr>\n", xml::escape(p.name)));
+		for (keys, cost) in top_keys {
+			s.push_str(&format!("<tr><td><code>{}</code></td><td>{:.1}</td></tr>\n", xml::escape(&ngram(keys)), cost));
+		}
+		s.push_str("</table>\n");
+	}
+	s.push_str("</div>\n");
+
+	s.push_str("<h2>Reference layouts</h2>\n<table>\n<tr><th>layout</th><th>total</th><th>scaled</th><th>against this layout</th></tr>\n");
+	let mut rows: Vec<(&str, f64, f64, bool)> = report.references.iter()
+		.map(|r| (&r.0[..], (r.1).0, (r.1).1, false))
+		.collect();
+	rows.push((report.name, total, scaled, true));
+	rows.sort_by(|a, b| a.2.partial_cmp(&b.2).unwrap_or(Ordering::Equal));
+	for (name, t, sc, this) in rows {
+		let against = if this { String::new() } else { format!("{:+.2}%", (t - total) / total.abs() * 100.0) };
+		s.push_str(&format!("<tr{}><td>{}</td><td>{:.1}</td><td>{:.4}</td><td>{}</td></tr>\n",
+			if this { " class=\"this\"" } else { "" }, xml::escape(name), t, sc, against));
+	}
+	s.push_str("</table>\n");
+
+	s.push_str("<p class=\"muted\">Generated by keygen.</p>\n</body>\n</html>\n");
+	s
+}
+
+// A bar as long as `value` is against `max`; rewards, below zero, are green.
+fn bar(value: f64, max: f64)
+-> String
+{
+	let width = if max > 0.0 { 100.0 * value.abs() / max } else { 0.0 };
+	format!("<div class=\"bar\"><span{} style=\"width: {:.1}%\"></span></div>",
+		if value < 0.0 { " class=\"reward\"" } else { "" }, width)
+}
+
+fn percent(value: f64, total: f64)
+-> String
+{
+	if total == 0.0 {
+		String::new()
+	} else {
+		format!("{:.1}%", 100.0 * value / total)
+	}
+}
+
+// An n-gram as displayed, with spaces made visible.
+fn ngram(keys: &str)
+-> String
+{
+	layout::key_labels(keys).replace(' ', "␣")
+}
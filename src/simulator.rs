@@ -1,218 +1,143 @@
-/// Applies the math in annealing.rs to keyboard layouts.
-
-
-extern crate rand;
-
-use self::rand::random;
-use std::cmp::Ordering;
-use std::collections::LinkedList;
+//! Runs optimisers on keyboard layouts and prints what they find.
 
+use best::BestLayouts;
+use hillclimb::HillClimb;
 use layout;
+use optimizer;
+use optimizer::Optimizer;
+use optimizer::Problem;
 use penalty;
-use annealing;
-
-struct BestLayoutsEntry
-{
-	layout:  layout::Layout,
-	penalty: f64,
-}
-
-impl BestLayoutsEntry
-{
-	fn cmp(&self, other: &BestLayoutsEntry)
-	-> Ordering
-	{
-		match self.penalty.partial_cmp(&other.penalty) {
-			Some(ord) => ord,
-			None => Ordering::Equal
-		}
-	}
-}
-
-pub fn simulate<'a>(
-	quartads:    &penalty::QuartadList<'a>,
-	len:          usize,
+use output;
+use output::Json;
+use output::Output;
+use output::Scored;
+
+// Runs `optimizer` from `init_layout`, and prints the best layouts it finds
+// with the seed it was given.
+pub fn simulate(
+	optimizer:   &mut dyn Optimizer,
+	problem:     &Problem,
 	init_layout: &layout::Layout,
-	penalties:   &Vec<penalty::KeyPenalty<'a>>,
 	debug:        bool,
-	top_layouts:  usize,
-	num_swaps:    usize)
+	seed:         u64,
+	output:      &Output)
 {
-	let penalty = penalty::calculate_penalty(&quartads, len, init_layout, penalties, true);
+	let penalty = penalty::calculate_penalty(problem.quartads, problem.len, init_layout, problem.penalties, true);
 
-	if debug {
+	if debug && output.is_text() {
 		println!("Initial layout:");
 		print_result(init_layout, &penalty);
 	}
 
-	// Keep track of the best layouts we've encountered.
-	let mut best_layouts: LinkedList<BestLayoutsEntry> = LinkedList::new();
-
-	let mut accepted_layout = init_layout.clone();
-	let mut accepted_penalty = penalty.1;
-	for i in annealing::get_simulation_range() {
-		// Copy and shuffle this iteration of the layout.
-		let mut curr_layout = accepted_layout.clone();
-		curr_layout.shuffle(random::<usize>() % num_swaps + 1);
-
-		// Calculate penalty.
-		let curr_layout_copy = curr_layout.clone();
-		let penalty = penalty::calculate_penalty(&quartads, len, &curr_layout, penalties, false);
-		let scaled_penalty = penalty.1;
-
-		// Probabilistically accept worse transitions; always accept better
-		// transitions.
-		if annealing::accept_transition(scaled_penalty - accepted_penalty, i) {
-			if debug {
-				println!("Iteration {} accepted with penalty {}", i, scaled_penalty);
-			}
-
-			accepted_layout = curr_layout_copy.clone();
-			accepted_penalty = scaled_penalty;
+	optimizer::run(optimizer, init_layout, penalty.1, debug, |_| ());
 
-			// Insert this layout into best layouts.
-			let new_entry = BestLayoutsEntry {
-				layout: curr_layout_copy,
-				penalty: penalty.1,
-			};
-			best_layouts = list_insert_ordered(best_layouts, new_entry);
-
-			// Limit best layouts list length.
-			while best_layouts.len() > top_layouts {
-				best_layouts.pop_back();
-			}
+	let results = score(problem, optimizer.best());
+	if output.is_text() {
+		println!();
+		println!("Seed: {}", seed);
+		for (layout, penalty) in results.iter() {
+			println!();
+			print_result(layout, penalty);
 		}
-	}
-
-	for entry in best_layouts.into_iter() {
-		let layout = entry.layout;
-		let penalty = penalty::calculate_penalty(&quartads, len, &layout, penalties, true);
-		println!("");
-		print_result(&layout, &penalty);
+	} else {
+		output.print_results(vec![("seed", Json::from(seed))], &ranked(&results));
 	}
 }
 
-pub fn refine<'a>(
-	quartads:    &penalty::QuartadList<'a>,
-	len:          usize,
+// Hill climbs from `init_layout`, printing the best layouts found after each
+// round and then the layout it ends up at.
+pub fn refine(
+	problem:     &Problem,
 	init_layout: &layout::Layout,
-	penalties:   &Vec<penalty::KeyPenalty<'a>>,
 	debug:        bool,
 	top_layouts:  usize,
-	num_swaps:    usize)
+	num_swaps:    usize,
+	min_distance: usize,
+	output:      &Output)
 {
-	let penalty = penalty::calculate_penalty(&quartads, len, init_layout, penalties, true);
-
-	println!("Initial layout:");
-	print_result(init_layout, &penalty);
+	let penalty = penalty::calculate_penalty(problem.quartads, problem.len, init_layout, problem.penalties, true);
 
-	let mut curr_layout = init_layout.clone();
-	let mut curr_penalty = penalty.1;
-
-	loop {
-		// Test every layout within `num_swaps` swaps of the initial layout.
-		let mut best_layouts: LinkedList<BestLayoutsEntry> = LinkedList::new();
-		let permutations = layout::LayoutPermutations::new(&curr_layout, num_swaps);
-		for (i, layout) in permutations.enumerate() {
-			let penalty = penalty::calculate_penalty(&quartads, len, &layout, penalties, false);
-
-			if debug {
-				println!("Iteration {}: {}", i, penalty.1);
-			}
-
-			// Insert this layout into best layouts.
-			let new_entry = BestLayoutsEntry {
-				layout: layout,
-				penalty: penalty.1,
-			};
-			best_layouts = list_insert_ordered(best_layouts, new_entry);
+	if output.is_text() {
+		println!("Initial layout:");
+		print_result(init_layout, &penalty);
+	} else {
+		output.print_results(stage("initial", 0), &[Scored {
+			name: "initial".to_string(),
+			layout: init_layout,
+			penalty: &penalty,
+		}]);
+	}
 
-			// Limit best layouts list length.
-			while best_layouts.len() > top_layouts {
-				best_layouts.pop_back();
+	let mut climb = HillClimb::new(*problem, top_layouts, min_distance, num_swaps);
+	optimizer::run(&mut climb, init_layout, penalty.1, debug, |climb| {
+		let results = score(problem, climb.best());
+		if output.is_text() {
+			for (layout, penalty) in results.iter() {
+				println!();
+				print_result(layout, penalty);
 			}
-		}
-
-		// Print the top layouts.
-		for entry in best_layouts.iter() {
-			let ref layout = entry.layout;
-			let penalty = penalty::calculate_penalty(&quartads, len, &layout, penalties, true);
-			println!("");
-			print_result(&layout, &penalty);
-		}
-
-		// Keep going until swapping doesn't get us any more improvements.
-		let best = best_layouts.pop_front().unwrap();
-		if curr_penalty <= best.penalty {
-			break;
 		} else {
-			curr_layout = best.layout;
-			curr_penalty = best.penalty;
+			output.print_results(stage("round", climb.progress().step), &ranked(&results));
 		}
+	});
+
+	let winner = climb.current().unwrap_or(init_layout);
+	if output.is_text() {
+		println!();
+		println!("Ultimate winner:");
+		println!("{}", winner);
+	} else {
+		let penalty = penalty::calculate_penalty(problem.quartads, problem.len, winner, problem.penalties, true);
+		output.print_results(stage("winner", climb.progress().step), &[Scored {
+			name: "winner".to_string(),
+			layout: winner,
+			penalty: &penalty,
+		}]);
 	}
-
-	println!("");
-	println!("Ultimate winner:");
-	println!("{}", curr_layout);
 }
 
-pub fn print_result<'a>(
-	layout: &'a layout::Layout,
-	penalty: &'a (f64, f64, Vec<penalty::KeyPenaltyResult<'a>>))
+pub fn print_result(
+	layout: &layout::Layout,
+	penalty: &(f64, f64, Vec<penalty::KeyPenaltyResult>))
 {
 	println!("{}", layout);
 
-	let (ref total, ref scaled, ref penalties) = *penalty;
+	let (total, scaled, penalties) = penalty;
 	println!("total: {}; scaled: {}", total, scaled);
 	for penalty in penalties {
 		print!("{}  / ", penalty);
-		let mut high_keys: Vec<(&str, f64)> = penalty.high_keys.iter().map(|x| (*x.0, *x.1)).collect();
-		high_keys.sort_by(|a, b|
-			match b.1.abs().partial_cmp(&a.1.abs()) {
-				Some(c) => c,
-				None => Ordering::Equal
-			});
-		for key in high_keys.iter().take(5) {
-			let (k, v) = *key;
-			print!(" {}: {};", k, v);
+		for (k, v) in penalty.top_keys(output::TOP_KEYS) {
+			print!(" {}: {};", layout::key_labels(k), v);
 		}
-		println!("");
+		println!();
 	}
 }
 
-// Take ownership of the list and give it back as a hack to make the borrow checker happy :^)
-
-fn list_insert_ordered(mut list: LinkedList<BestLayoutsEntry>, entry: BestLayoutsEntry)
--> LinkedList<BestLayoutsEntry>
+// Calculates the detailed penalties of the best layouts.
+fn score(problem: &Problem, best: &BestLayouts)
+-> Vec<(layout::Layout, output::Penalty)>
 {
-	if let Some(first) = list.front() {
-		let cmp = entry.cmp(first);
-		if cmp == Ordering::Less {
-			list.push_front(entry);
-			return list;
-		}
-	}
-	{
-		// Find where to add our new entry to, since the list is sorted.
-		let mut cursor = list.cursor_front_mut();
-		loop {
-			{
-				let opt_next = cursor.peek_next();
-				if let Some(next) = opt_next {
-					let cmp = entry.cmp(next);
-					if cmp == Ordering::Less {
-						break;
-					}
-				} else {
-					break;
-				}
-			}
+	best.layouts()
+		.map(|l| (l.clone(), penalty::calculate_penalty(problem.quartads, problem.len, l, problem.penalties, true)))
+		.collect()
+}
 
-			cursor.move_next();
-		}
+// Results named by their rank.
+fn ranked(results: &[(layout::Layout, output::Penalty)])
+-> Vec<Scored<'_>>
+{
+	results.iter().enumerate()
+		.map(|(i, (layout, penalty))| Scored {
+			name: format!("#{}", i + 1),
+			layout,
+			penalty,
+		})
+		.collect()
+}
 
-		// Add to list.
-		cursor.insert_after(entry);
-	}
-	list
+// The fields that say which stage of refining results come from.
+fn stage(name: &str, round: usize)
+-> Vec<(&str, Json)>
+{
+	vec![("stage", Json::from(name)), ("round", Json::from(round))]
 }
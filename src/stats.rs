@@ -0,0 +1,182 @@
+//! Statistics about what a corpus asks the optimiser to optimise for.
+
+use std::collections::HashMap;
+
+use corpus;
+use corpus::CorpusFormat;
+use error::Result;
+use layout;
+use layout::Layout;
+use output::Json;
+use output::Output;
+use output::OutputFormat;
+use penalty::QuartadList;
+
+static NGRAM_NAMES: [&str; 4] = ["characters", "bigrams", "trigrams", "quadgrams"];
+
+pub struct CorpusStats
+{
+	// Keystrokes that fall on the layout.
+	pub chars:   usize,
+	// Characters that are not on the layout and were dropped.
+	pub dropped: HashMap<char, usize>,
+	// `ngrams[n - 1]` holds the n-grams of length n, for n = 1..4.
+	pub ngrams:  [HashMap<String, usize>; 4],
+}
+
+impl CorpusStats
+{
+	// Every quartad ends at exactly one keystroke, and its suffixes are the
+	// 1- to 4-grams ending there, so the n-gram counts can be recovered from
+	// the quartad list exactly. Quartads only hold the ASCII keys of a
+	// position map, so they can be sliced by byte.
+	pub fn new(quartads: &QuartadList, dropped: &HashMap<char, usize>)
+	-> CorpusStats
+	{
+		let QuartadList(ref quartads) = *quartads;
+		let mut ngrams: [HashMap<String, usize>; 4] = Default::default();
+		let mut chars = 0;
+		for (quartad, count) in quartads {
+			chars += *count;
+			let len = quartad.len();
+			for n in 1..(len + 1) {
+				*ngrams[n - 1].entry(quartad[(len - n)..].to_string()).or_insert(0) += *count;
+			}
+		}
+
+		CorpusStats {
+			chars,
+			dropped: dropped.clone(),
+			ngrams,
+		}
+	}
+
+	pub fn dropped_total(&self)
+	-> usize
+	{
+		self.dropped.values().sum()
+	}
+
+	// The `top` most frequent n-grams of length `n`, most frequent first.
+	pub fn top_ngrams(&self, n: usize, top: usize)
+	-> Vec<(&str, usize)>
+	{
+		let mut ngrams: Vec<(&str, usize)> = self.ngrams[n - 1].iter()
+			.map(|(k, v)| (&k[..], *v))
+			.collect();
+		ngrams.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+		ngrams.truncate(top);
+		ngrams
+	}
+
+	pub fn top_dropped(&self, top: usize)
+	-> Vec<(char, usize)>
+	{
+		let mut dropped: Vec<(char, usize)> = self.dropped.iter().map(|(k, v)| (*k, *v)).collect();
+		dropped.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+		dropped.truncate(top);
+		dropped
+	}
+}
+
+// Reads the statistics of the corpus named by `spec` for `layout`, and the
+// number of files it has. They need the characters that quartad extraction
+// drops, so they bypass the cache.
+pub fn read(spec: &str, format: &CorpusFormat, layout: &Layout)
+-> Result<(CorpusStats, usize)>
+{
+	let paths = corpus::expand_paths(spec)?;
+	let (quartads, _, dropped) = corpus::read_paths(&paths, format, &layout.get_position_map())?;
+	Ok((CorpusStats::new(&quartads, &dropped), paths.len()))
+}
+
+pub fn print_stats(stats: &CorpusStats, top: usize)
+{
+	let dropped = stats.dropped_total();
+	let total = stats.chars + dropped;
+	println!("characters: {}; on layout: {}; outside layout: {} ({:.3}%)",
+		total, stats.chars, dropped, percent(dropped, total));
+	println!("distinct: {} characters, {} bigrams, {} trigrams, {} quadgrams",
+		stats.ngrams[0].len(), stats.ngrams[1].len(), stats.ngrams[2].len(), stats.ngrams[3].len());
+
+	for (i, name) in NGRAM_NAMES.iter().enumerate() {
+		let n = i + 1;
+		let n_total: usize = stats.ngrams[i].values().sum();
+		println!();
+		println!("Top {} ({} total):", name, n_total);
+		for (ngram, count) in stats.top_ngrams(n, top) {
+			println!("  {:<8} {:>10}  {:>7.3}%", format!("{:?}", layout::key_labels(ngram)), count, percent(count, n_total));
+		}
+	}
+
+	println!();
+	println!("Top characters outside layout:");
+	for (c, count) in stats.top_dropped(top) {
+		println!("  {:<8} {:>10}  {:>7.3}%", format!("{:?}", layout::key_label(c)), count, percent(count, total));
+	}
+}
+
+// Prints the same statistics as JSON or CSV.
+pub fn output_stats(output: &Output, stats: &CorpusStats, files: usize, top: usize)
+{
+	let dropped = stats.dropped_total();
+	let total = stats.chars + dropped;
+
+	// (kind, n-gram, count, percent) for each n-gram that is reported.
+	let mut rows: Vec<(&str, String, usize, f64)> = Vec::new();
+	for (i, name) in NGRAM_NAMES.iter().enumerate() {
+		let n_total: usize = stats.ngrams[i].values().sum();
+		for (ngram, count) in stats.top_ngrams(i + 1, top) {
+			rows.push((name, layout::key_labels(ngram), count, percent(count, n_total)));
+		}
+	}
+	for (c, count) in stats.top_dropped(top) {
+		rows.push(("outside layout", layout::key_label(c).to_string(), count, percent(count, total)));
+	}
+
+	match output.format {
+		OutputFormat::Text => print_stats(stats, top),
+		OutputFormat::Json => {
+			let entries = |kind: &str| Json::Arr(rows.iter()
+				.filter(|r| r.0 == kind)
+				.map(|r| Json::obj(vec![
+					("ngram", Json::from(&r.1[..])),
+					("count", Json::from(r.2)),
+					("percent", Json::from(r.3)),
+				]))
+				.collect());
+			let mut distinct = Vec::new();
+			let mut top_ngrams = Vec::new();
+			for (i, name) in NGRAM_NAMES.iter().enumerate() {
+				distinct.push((*name, Json::from(stats.ngrams[i].len())));
+				top_ngrams.push((*name, entries(name)));
+			}
+			output.print_json(vec![
+				("files", Json::from(files)),
+				("characters", Json::from(total)),
+				("on_layout", Json::from(stats.chars)),
+				("outside_layout", Json::from(dropped)),
+				("distinct", Json::obj(distinct)),
+				("top", Json::obj(top_ngrams)),
+				("top_outside_layout", entries("outside layout")),
+			]);
+		},
+		OutputFormat::Csv => {
+			let header: Vec<String> = ["kind", "ngram", "count", "percent"].iter().map(|s| s.to_string()).collect();
+			let rows: Vec<Vec<String>> = rows.into_iter()
+				.map(|r| vec![r.0.to_string(), r.1, r.2.to_string(), r.3.to_string()])
+				.collect();
+			output.print_csv(&header, &rows);
+		},
+	}
+}
+
+fn percent(n: usize, total: usize)
+-> f64
+{
+	if total == 0 {
+		0.0
+	} else {
+		100.0 * n as f64 / total as f64
+	}
+}
@@ -0,0 +1,186 @@
+//! Draws a layout as an SVG heat map.
+//!
+//! Keys are drawn in the split 3x5+1 shape of `KeyMap`, each with its lower
+//! character at the bottom and its upper character, if different, above it,
+//! and coloured from white to red by the value given for it. Arcs may join
+//! pairs of keys, such as the most costly same-finger bigrams.
+
+use layout;
+use layout::Layout;
+use penalty;
+use penalty::QuartadList;
+use registry::NamedLayout;
+use xml;
+
+// Size of a key, the space between keys, and the space between the hands.
+const KEY:    f64 = 54.0;
+const PITCH:  f64 = 60.0;
+const SPLIT:  f64 = 40.0;
+const MARGIN: f64 = 20.0;
+const TITLE:  f64 = 30.0;
+
+// Colours of the smallest and largest values.
+const COLD: (f64, f64, f64) = (255.0, 255.0, 255.0);
+const HOT:  (f64, f64, f64) = (215.0, 48.0, 39.0);
+
+// What colours the keys of a heat map.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Heat
+{
+	Frequency,
+	Penalty,
+}
+
+pub fn parse_heat(s: &str)
+-> Option<Heat>
+{
+	match s {
+		"frequency" => Some(Heat::Frequency),
+		"penalty" => Some(Heat::Penalty),
+		_ => None,
+	}
+}
+
+pub struct HeatMap<'a>
+{
+	pub title:  &'a str,
+	// What the values are, e.g. "keystrokes".
+	pub legend: &'a str,
+	pub values: [f64; 34],
+	// Pairs of positions to join, and how much weight each has.
+	pub arcs:   Vec<(usize, usize, f64)>,
+}
+
+// An SVG heat map of `l`, with arcs joining the `num_arcs` costliest
+// same-finger bigrams.
+pub fn heatmap(quartads: &QuartadList, len: usize, l: &NamedLayout, heat: Heat, num_arcs: usize)
+-> String
+{
+	let (values, legend) = match heat {
+		Heat::Frequency => (penalty::key_counts(quartads, &l.layout), "keystrokes"),
+		Heat::Penalty => (penalty::key_penalties(quartads, &l.layout), "penalty"),
+	};
+
+	let mut arcs = Vec::new();
+	if num_arcs > 0 {
+		let penalties = penalty::init();
+		let (_, _, results) = penalty::calculate_penalty(quartads, len, &l.layout, &penalties, true);
+		let position_map = l.layout.get_position_map();
+		let position = |c: char| position_map.get_key_position(c).as_ref().map(|kp| kp.pos);
+		if let Some(same_finger) = results.iter().find(|p| p.name == "same finger") {
+			for (bigram, cost) in same_finger.top_keys(num_arcs) {
+				let mut chars = bigram.chars();
+				if let (Some(Some(a)), Some(Some(b))) = (chars.next().map(&position), chars.next().map(&position)) {
+					arcs.push((a, b, cost));
+				}
+			}
+		}
+	}
+
+	let map = HeatMap {
+		title: &l.name,
+		legend,
+		values,
+		arcs,
+	};
+	render(&l.layout, &map)
+}
+
+pub fn render(layout: &Layout, map: &HeatMap)
+-> String
+{
+	let width = 2.0 * MARGIN + 10.0 * PITCH + KEY + SPLIT;
+	let height = TITLE + 2.0 * MARGIN + 3.0 * PITCH + KEY + 20.0;
+
+	let mut s = String::new();
+	s.push_str(&format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\" font-family=\"sans-serif\">\n",
+		width, height, width, height));
+	s.push_str(&format!("  <rect width=\"{}\" height=\"{}\" fill=\"#fafafa\"/>\n", width, height));
+	s.push_str(&format!("  <text x=\"{}\" y=\"{}\" font-size=\"16\" font-weight=\"bold\">{}</text>\n",
+		MARGIN, MARGIN + 8.0, xml::escape(map.title)));
+
+	// Shares are relative to the total, and colours to the range of values,
+	// which for penalties may include rewards below zero.
+	let total: f64 = map.values.iter().sum();
+	let min = map.values.iter().cloned().fold(0.0, f64::min);
+	let max = map.values.iter().cloned().fold(0.0, f64::max);
+
+	for pos in 0..34 {
+		let (x, y) = corner(pos);
+		let value = map.values[pos];
+		let heat = if max > min { (value - min) / (max - min) } else { 0.0 };
+		s.push_str(&format!("  <g transform=\"translate({},{})\">\n", x, y));
+		s.push_str(&format!("    <rect width=\"{}\" height=\"{}\" rx=\"6\" fill=\"{}\" stroke=\"#555\"/>\n", KEY, KEY, colour(heat)));
+
+		let lower = layout.lower()[pos];
+		let upper = layout.upper()[pos];
+		s.push_str(&format!("    <text x=\"8\" y=\"{}\" font-size=\"20\">{}</text>\n", KEY - 10.0, xml::escape(&label(lower))));
+		if upper != lower {
+			s.push_str(&format!("    <text x=\"8\" y=\"18\" font-size=\"13\" fill=\"#444\">{}</text>\n", xml::escape(&label(upper))));
+		}
+		if total != 0.0 && value != 0.0 {
+			s.push_str(&format!("    <text x=\"{}\" y=\"{}\" font-size=\"10\" text-anchor=\"end\" fill=\"#333\">{:.1}%</text>\n",
+				KEY - 5.0, KEY - 6.0, 100.0 * value / total));
+		}
+		s.push_str("  </g>\n");
+	}
+
+	// Arcs bow to the side, so that they don't hide the keys between their
+	// ends, and are thicker the more weight they have.
+	let heaviest = map.arcs.iter().map(|a| a.2.abs()).fold(0.0, f64::max);
+	for &(from, to, weight) in map.arcs.iter() {
+		let (x1, y1) = centre(from);
+		let (x2, y2) = centre(to);
+		let (dx, dy) = (x2 - x1, y2 - y1);
+		let (cx, cy) = ((x1 + x2) / 2.0 + dy * 0.4, (y1 + y2) / 2.0 - dx * 0.4);
+		let stroke = if heaviest > 0.0 { 1.0 + 5.0 * weight.abs() / heaviest } else { 1.0 };
+		s.push_str(&format!("  <path d=\"M {:.1} {:.1} Q {:.1} {:.1} {:.1} {:.1}\" fill=\"none\" stroke=\"#2166ac\" stroke-opacity=\"0.7\" stroke-width=\"{:.1}\" stroke-linecap=\"round\"/>\n",
+			x1, y1, cx, cy, x2, y2, stroke));
+	}
+
+	s.push_str(&format!("  <text x=\"{}\" y=\"{}\" font-size=\"12\" fill=\"#333\">Colour: share of {}{}</text>\n",
+		MARGIN, height - MARGIN + 4.0, xml::escape(map.legend),
+		if map.arcs.is_empty() { "" } else { "; arcs: costliest same-finger bigrams" }));
+	s.push_str("</svg>\n");
+	s
+}
+
+// The top left corner of the key at `pos`. The thumb keys sit under the
+// innermost columns.
+fn corner(pos: usize)
+-> (f64, f64)
+{
+	let (row, column) = match pos {
+		0..=10 => (0, pos),
+		11..=21 => (1, pos - 11),
+		22..=31 => (2, pos - 22),
+		_ => (3, pos - 32 + 4),
+	};
+	let split = if column >= 5 { SPLIT } else { 0.0 };
+	(MARGIN + column as f64 * PITCH + split, TITLE + MARGIN + row as f64 * PITCH)
+}
+
+fn centre(pos: usize)
+-> (f64, f64)
+{
+	let (x, y) = corner(pos);
+	(x + KEY / 2.0, y + KEY / 2.0)
+}
+
+// The colour of a key, from `heat` between 0 and 1.
+fn colour(heat: f64)
+-> String
+{
+	let mix = |a: f64, b: f64| (a + (b - a) * heat).round() as u8;
+	format!("#{:02x}{:02x}{:02x}", mix(COLD.0, HOT.0), mix(COLD.1, HOT.1), mix(COLD.2, HOT.2))
+}
+
+fn label(kc: char)
+-> String
+{
+	match kc {
+		'\0' => String::new(),
+		' ' => "␣".to_string(),
+		_ => layout::key_label(kc).to_string(),
+	}
+}
@@ -0,0 +1,221 @@
+//! Tabu search: each step takes the best swap of two keys, even one that makes
+//! the layout worse, but a key may not go back to where it just was for a
+//! while, so that the search climbs out of a local minimum instead of falling
+//! straight back in.
+
+extern crate rand;
+
+use std::collections::HashMap;
+use std::f64;
+use self::rand::Rng;
+use self::rand::StdRng;
+
+use best::BestLayouts;
+use layout::Layout;
+use optimizer;
+use optimizer::Optimizer;
+use optimizer::Problem;
+use optimizer::Progress;
+use penalty::PenaltyIndex;
+
+// How many steps a key may not return to a position it left, and how many
+// steps a search takes.
+pub const TENURE: usize = 10;
+pub const STEPS:  usize = 500;
+
+// A key, by its lower and upper characters.
+type Key = (char, char);
+
+pub struct TabuList
+{
+	tenure: usize,
+	// The step until which each key may not go to each position.
+	until:  HashMap<(Key, usize), usize>,
+}
+
+impl TabuList
+{
+	pub fn new(tenure: usize)
+	-> TabuList
+	{
+		TabuList {
+			tenure,
+			until: HashMap::new(),
+		}
+	}
+
+	// Records that at `step`, `key` left position `pos`.
+	pub fn left(&mut self, key: Key, pos: usize, step: usize)
+	{
+		self.until.insert((key, pos), step + self.tenure);
+	}
+
+	// Whether, at `step`, moving `key` to `pos` is tabu.
+	pub fn is_tabu(&self, key: Key, pos: usize, step: usize)
+	-> bool
+	{
+		self.until.get(&(key, pos)).is_some_and(|&until| step <= until)
+	}
+}
+
+// The aspiration criterion: a tabu swap may still be taken if it gives a
+// better layout than any found so far.
+pub fn allowed(tabu: bool, penalty: f64, best_penalty: f64)
+-> bool
+{
+	!tabu || penalty < best_penalty
+}
+
+pub struct Tabu<'a>
+{
+	problem:      Problem<'a>,
+	steps:        usize,
+	rng:          StdRng,
+	best:         BestLayouts,
+	tabu_list:    TabuList,
+	current:      Option<(Layout, f64)>,
+	// The current layout's index, updated with each swap.
+	index:        Option<PenaltyIndex>,
+	best_penalty: f64,
+	step:         usize,
+}
+
+impl <'a> Tabu<'a>
+{
+	pub fn new(problem: Problem<'a>, top_layouts: usize, min_distance: usize, tenure: usize, steps: usize, seed: u64)
+	-> Tabu<'a>
+	{
+		Tabu {
+			problem,
+			steps,
+			rng: optimizer::seeded_rng(seed),
+			best: BestLayouts::with_min_distance(top_layouts, min_distance),
+			tabu_list: TabuList::new(tenure),
+			current: None,
+			index: None,
+			best_penalty: f64::INFINITY,
+			step: 0,
+		}
+	}
+}
+
+impl <'a> Optimizer for Tabu<'a>
+{
+	fn name(&self)
+	-> &'static str
+	{
+		"tabu"
+	}
+
+	fn init(&mut self, layout: &Layout, penalty: f64)
+	{
+		self.current = Some((layout.clone(), penalty));
+		self.index = Some(PenaltyIndex::new(self.problem.quartads, layout));
+		self.best_penalty = penalty;
+		self.step = 0;
+	}
+
+	fn step(&mut self)
+	-> bool
+	{
+		if self.step >= self.steps {
+			return false;
+		}
+		let step = self.step;
+		self.step += 1;
+
+		let curr_layout = self.current.as_ref().expect("tabu search before init").0.clone();
+		let index = self.index.as_mut().expect("tabu search before init");
+		let key = |p: usize| (curr_layout.lower()[p], curr_layout.upper()[p]);
+		let positions = self.problem.mask.positions();
+		let len = self.problem.len as f64;
+
+		// The best allowed swap, with ties broken at random.
+		let mut chosen: Option<(usize, usize, f64)> = None;
+		let mut ties = 0;
+		for (n, &i) in positions.iter().enumerate() {
+			for &j in &positions[n + 1..] {
+				if key(i) == key(j) {
+					continue;
+				}
+				let penalty = (index.total() + index.cycle_change(&[i, j])) / len;
+				let tabu = self.tabu_list.is_tabu(key(i), j, step) || self.tabu_list.is_tabu(key(j), i, step);
+				if !allowed(tabu, penalty, self.best_penalty) {
+					continue;
+				}
+				match chosen {
+					Some((_, _, p)) if penalty > p => continue,
+					Some((_, _, p)) if penalty == p => {
+						ties += 1;
+						if self.rng.gen::<usize>() % ties != 0 {
+							continue;
+						}
+					},
+					_ => ties = 1,
+				}
+				chosen = Some((i, j, penalty));
+			}
+		}
+
+		// With every swap tabu, there is nowhere left to go.
+		let (i, j, penalty) = match chosen {
+			Some(c) => c,
+			None => return false,
+		};
+		self.tabu_list.left(key(i), i, step);
+		self.tabu_list.left(key(j), j, step);
+		let layout = curr_layout.rearranged(&[[i, j]]);
+		index.apply(&[i, j]);
+
+		if penalty < self.best_penalty {
+			self.best_penalty = penalty;
+		}
+		self.best.insert(layout.clone(), penalty);
+		self.current = Some((layout, penalty));
+		true
+	}
+
+	fn best(&self)
+	-> &BestLayouts
+	{
+		&self.best
+	}
+
+	fn progress(&self)
+	-> Progress
+	{
+		Progress {
+			step:    self.step,
+			penalty: self.current.as_ref().map_or(f64::NAN, |c| c.1),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn a_move_is_tabu_for_its_tenure()
+	{
+		let mut tabu_list = TabuList::new(3);
+		let key = ('a', 'A');
+		tabu_list.left(key, 5, 10);
+		for step in 11..14 {
+			assert!(tabu_list.is_tabu(key, 5, step), "step {}", step);
+		}
+		assert!(!tabu_list.is_tabu(key, 5, 14));
+		assert!(!tabu_list.is_tabu(key, 6, 11));
+		assert!(!tabu_list.is_tabu(('b', 'B'), 5, 11));
+	}
+
+	#[test]
+	fn a_tabu_move_is_allowed_only_if_it_beats_the_best()
+	{
+		assert!(allowed(false, 2.0, 1.0));
+		assert!(allowed(true, 0.5, 1.0));
+		assert!(!allowed(true, 1.0, 1.0));
+		assert!(!allowed(true, 2.0, 1.0));
+	}
+}
@@ -0,0 +1,174 @@
+//! Parallel tempering: several annealing chains, or replicas, each at a fixed
+//! temperature from the annealing schedule, that now and then swap layouts so
+//! that a good layout found at a high temperature cools down, and one that a
+//! cold replica is stuck at warms up.
+
+extern crate rand;
+
+use std::f64;
+use self::rand::Rng;
+use self::rand::StdRng;
+
+use annealing;
+use best::BestLayouts;
+use layout::Layout;
+use optimizer;
+use optimizer::Optimizer;
+use optimizer::Problem;
+use optimizer::Progress;
+
+// The number of replicas, the number of steps, each of which moves every
+// replica once, and how many steps there are between exchanges.
+pub const REPLICAS: usize = 8;
+pub const STEPS:    usize = 2000;
+pub const EXCHANGE: usize = 10;
+
+// The temperatures of `replicas` replicas, hottest first, spread evenly over
+// the annealing schedule from its first temperature to its last.
+pub fn ladder(replicas: usize)
+-> Vec<f64>
+{
+	if replicas < 2 {
+		return vec![annealing::temperature(annealing::N)];
+	}
+	(0..replicas)
+		.map(|r| annealing::temperature(annealing::N * r / (replicas - 1)))
+		.collect()
+}
+
+// Whether replicas at temperatures `t0` and `t1`, with layouts whose penalties
+// are `e0` and `e1`, swap layouts: always if that moves the better layout to
+// the colder replica, and otherwise with the Metropolis chance exp(-dE), where
+// dE = (e0 - e1) (1/t1 - 1/t0).
+pub fn exchange<R: Rng>(e0: f64, t0: f64, e1: f64, t1: f64, rng: &mut R)
+-> bool
+{
+	let de = (e0 - e1) * (1.0 / t1 - 1.0 / t0);
+	de <= 0.0 || rng.next_f64() < (-de).exp()
+}
+
+pub struct Tempering<'a>
+{
+	problem:      Problem<'a>,
+	num_swaps:    usize,
+	steps:        usize,
+	rng:          StdRng,
+	best:         BestLayouts,
+	temperatures: Vec<f64>,
+	// Each replica's layout and its penalty, in the order of `temperatures`.
+	replicas:     Vec<(Layout, f64)>,
+	step:         usize,
+}
+
+impl <'a> Tempering<'a>
+{
+	#[allow(clippy::too_many_arguments)]
+	pub fn new(problem: Problem<'a>, top_layouts: usize, min_distance: usize, replicas: usize, steps: usize, num_swaps: usize, seed: u64)
+	-> Tempering<'a>
+	{
+		Tempering {
+			problem,
+			num_swaps,
+			steps,
+			rng: optimizer::seeded_rng(seed),
+			best: BestLayouts::with_min_distance(top_layouts, min_distance),
+			temperatures: ladder(replicas),
+			replicas: Vec::with_capacity(replicas),
+			step: 0,
+		}
+	}
+}
+
+impl <'a> Optimizer for Tempering<'a>
+{
+	fn name(&self)
+	-> &'static str
+	{
+		"tempering"
+	}
+
+	fn init(&mut self, layout: &Layout, penalty: f64)
+	{
+		self.replicas = self.temperatures.iter()
+			.map(|_| (layout.clone(), penalty))
+			.collect();
+		self.step = 0;
+	}
+
+	fn step(&mut self)
+	-> bool
+	{
+		if self.step >= self.steps {
+			return false;
+		}
+		self.step += 1;
+
+		// Move each replica as annealing would at its temperature.
+		for (replica, &t) in self.replicas.iter_mut().zip(self.temperatures.iter()) {
+			let mut curr_layout = replica.0.clone();
+			let times = self.rng.gen::<usize>() % self.num_swaps + 1;
+			curr_layout.shuffle(times, self.problem.mask, &mut self.rng);
+			let penalty = self.problem.penalty(&curr_layout);
+
+			if annealing::accept_at(penalty - replica.1, t, &mut self.rng) {
+				*replica = (curr_layout.clone(), penalty);
+				self.best.insert(curr_layout, penalty);
+			}
+		}
+
+		// Offer each pair of neighbouring replicas an exchange, hottest first.
+		if self.step.is_multiple_of(EXCHANGE) {
+			for r in 1..self.replicas.len() {
+				let (e0, t0) = (self.replicas[r - 1].1, self.temperatures[r - 1]);
+				let (e1, t1) = (self.replicas[r].1, self.temperatures[r]);
+				if exchange(e0, t0, e1, t1, &mut self.rng) {
+					self.replicas.swap(r - 1, r);
+				}
+			}
+		}
+		true
+	}
+
+	fn best(&self)
+	-> &BestLayouts
+	{
+		&self.best
+	}
+
+	// The penalty of the best layout any replica is at.
+	fn progress(&self)
+	-> Progress
+	{
+		Progress {
+			step:    self.step,
+			penalty: self.replicas.iter().map(|r| r.1).fold(f64::NAN, f64::min),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn a_better_layout_on_the_hotter_replica_always_moves_down()
+	{
+		let mut rng = optimizer::seeded_rng(1);
+		for pair in ladder(REPLICAS).windows(2) {
+			for _ in 0..1000 {
+				assert!(exchange(1.0, pair[0], 1.5, pair[1], &mut rng));
+			}
+		}
+	}
+
+	#[test]
+	fn a_worse_layout_on_the_hotter_replica_moves_down_with_the_metropolis_chance()
+	{
+		// dE = (2 - 1) (1/1 - 1/10) = 0.9.
+		let mut rng = optimizer::seeded_rng(1);
+		let swaps = (0..10000).filter(|_| exchange(2.0, 10.0, 1.0, 1.0, &mut rng)).count();
+		let expected = 10000.0 * (-0.9f64).exp();
+		assert!((swaps as f64 - expected).abs() < 200.0, "{} swaps, expected {}", swaps, expected);
+	}
+}
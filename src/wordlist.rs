@@ -0,0 +1,320 @@
+//! Synthesises a quartad list from a word-frequency list, for languages where
+//! we have word counts but no large free-text corpus.
+//!
+//! The text is modelled as a stream of independently drawn words, each
+//! followed by a separator: usually a space, sometimes a punctuation mark and
+//! a space. Quartads within a word and its separator are counted exactly;
+//! quartads that reach back into the previous word use the distribution of
+//! word endings across the whole list.
+
+use std::collections::HashMap;
+use std::io::BufRead;
+
+use error::Error;
+use error::Result;
+use layout::LayoutPosMap;
+use penalty::QuartadList;
+
+// Number of times the distribution of preceding context is refined. Only words
+// shorter than a quartad depend on more than one preceding word, so this
+// converges very quickly.
+const CONTEXT_ITERATIONS: usize = 3;
+
+// Number of keystrokes in the modelled text. Counts are rounded to whole
+// numbers, so the word counts are scaled up to this first: otherwise rare
+// quartads would round to nothing, or all of them would for a list of
+// frequencies rather than counts.
+const KEYSTROKES: f64 = 1e9;
+
+// Slack for rounding when punctuation probabilities add up to exactly 1.
+const TOLERANCE: f64 = 1e-9;
+
+#[derive(Clone)]
+pub struct WordListConfig
+{
+	// Text between two words.
+	pub separator:   String,
+	// Punctuation that precedes the separator, with the probability of it
+	// following any given word.
+	pub punctuation: Vec<(String, f64)>,
+}
+
+impl Default for WordListConfig
+{
+	fn default()
+	-> WordListConfig
+	{
+		WordListConfig {
+			separator: " ".to_string(),
+			punctuation: Vec::new(),
+		}
+	}
+}
+
+impl WordListConfig
+{
+	// Parses a punctuation transition of the form `MARK:PROBABILITY`, e.g.
+	// `.:0.05`.
+	pub fn add_punctuation(&mut self, spec: &str)
+	-> Result<()>
+	{
+		let split = match spec.rfind(':') {
+			Some(i) if i > 0 => i,
+			_ => return Err(Error::config(format!("invalid punctuation {}; expected MARK:PROBABILITY", spec))),
+		};
+		let (mark, p) = (&spec[..split], &spec[(split + 1)..]);
+		let p = match p.parse::<f64>() {
+			Ok(p) if (0.0..=1.0).contains(&p) => p,
+			_ => return Err(Error::config(format!("invalid punctuation probability {}", p))),
+		};
+
+		let total: f64 = self.punctuation.iter().map(|x| x.1).sum();
+		if total + p > 1.0 + TOLERANCE {
+			return Err(Error::config("punctuation probabilities add up to more than 1"));
+		}
+		self.punctuation.push((mark.to_string(), p));
+		Ok(())
+	}
+
+	// Every separator that can follow a word, with its probability.
+	fn separators(&self)
+	-> Vec<(String, f64)>
+	{
+		let mut seps = Vec::new();
+		let mut plain = 1.0;
+		for (mark, p) in self.punctuation.iter() {
+			seps.push((format!("{}{}", mark, self.separator), *p));
+			plain -= p;
+		}
+		if plain > TOLERANCE {
+			seps.push((self.separator.clone(), plain));
+		}
+		seps
+	}
+
+	pub fn describe(&self)
+	-> String
+	{
+		format!("sep={:?} punct={:?}", self.separator, self.punctuation)
+	}
+}
+
+// Reads `word count` lines. Blank lines and lines starting with `#` are
+// ignored.
+pub fn read_words<R: BufRead>(reader: R, name: &str)
+-> Result<Vec<(String, f64)>>
+{
+	let mut words = Vec::new();
+	for (i, line) in reader.lines().enumerate() {
+		let line = line.map_err(|e| Error::corpus_line(e, name, i + 1))?;
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') {
+			continue;
+		}
+
+		let mut fields = line.split_whitespace();
+		let parsed = match (fields.next(), fields.next(), fields.next()) {
+			(Some(word), Some(count), None) => count.parse::<f64>().ok()
+				.filter(|c| *c >= 0.0)
+				.map(|c| (word.to_string(), c)),
+			_ => None,
+		};
+		match parsed {
+			Some(entry) => words.push(entry),
+			None => return Err(Error::corpus(name, i + 1, format!("expected `word count`, found {:?}", line))),
+		}
+	}
+
+	Ok(words)
+}
+
+// Builds the quartad list of the text modelled by `words` and `config`.
+// Returns the quartads, the expected length of the text in bytes and the
+// expected counts of characters that are not on the layout.
+pub fn synthesise(
+	words:        &[(String, f64)],
+	config:       &WordListConfig,
+	position_map: &LayoutPosMap)
+-> (QuartadList, usize, HashMap<char, usize>)
+{
+	// A unit is a word followed by one of its separators.
+	let mut units: Vec<(Vec<char>, f64)> = Vec::new();
+	let mut total_weight = 0.0;
+	for (word, count) in words {
+		for (sep, p) in config.separators() {
+			let unit: Vec<char> = word.chars().chain(sep.chars()).collect();
+			units.push((unit, count * p));
+			total_weight += count * p;
+		}
+	}
+
+	let mut quartads: HashMap<String, f64> = HashMap::new();
+	let mut dropped: HashMap<char, f64> = HashMap::new();
+	let mut len = 0.0;
+	let keystrokes: f64 = units.iter().map(|(unit, weight)| unit.len() as f64 * weight).sum();
+	if total_weight <= 0.0 || keystrokes <= 0.0 {
+		return (QuartadList(HashMap::new()), 0, HashMap::new());
+	}
+
+	let scale = KEYSTROKES / keystrokes;
+	for unit in units.iter_mut() {
+		unit.1 *= scale;
+	}
+	let total_weight = total_weight * scale;
+
+	// Quartads that end in the first three characters of a unit depend on
+	// the preceding text, so collect those prefixes and count the rest.
+	let mut prefixes: HashMap<Vec<char>, f64> = HashMap::new();
+	for (unit, weight) in units.iter() {
+		len += unit.iter().map(|c| c.len_utf8()).sum::<usize>() as f64 * weight;
+		for (j, c) in unit.iter().enumerate() {
+			if position_map.get_key_position(*c).is_none() {
+				*dropped.entry(*c).or_insert(0.0) += weight;
+			}
+			if j < 3 {
+				*prefixes.entry(unit[..(j + 1)].to_vec()).or_insert(0.0) += weight;
+			} else {
+				add_quartad(&mut quartads, &unit[(j - 3)..(j + 1)], *weight, position_map);
+			}
+		}
+	}
+
+	// A prefix of n characters only needs the last 4 - n characters of its
+	// context, so merge contexts that agree on those.
+	let context = preceding_context(&units, total_weight);
+	let mut contexts: Vec<HashMap<Vec<char>, f64>> = vec![HashMap::new(); 4];
+	for (tail, p) in context.iter() {
+		for (n, merged) in contexts.iter_mut().enumerate().skip(1) {
+			let key = tail[(tail.len().saturating_sub(4 - n))..].to_vec();
+			*merged.entry(key).or_insert(0.0) += p;
+		}
+	}
+	for (prefix, weight) in prefixes.iter() {
+		for (tail, p) in contexts[prefix.len()].iter() {
+			let text: Vec<char> = tail.iter().chain(prefix.iter()).cloned().collect();
+			add_quartad(&mut quartads, &text[(text.len().saturating_sub(4))..], weight * p, position_map);
+		}
+	}
+
+	let quartads = quartads.into_iter()
+		.map(|(k, v)| (k, v.round() as usize))
+		.filter(|x| x.1 > 0)
+		.collect();
+	let dropped = dropped.into_iter()
+		.map(|(k, v)| (k, v.round() as usize))
+		.filter(|x| x.1 > 0)
+		.collect();
+
+	(QuartadList(quartads), len.round() as usize, dropped)
+}
+
+// The distribution of the last three characters of text preceding a unit.
+fn preceding_context(units: &[(Vec<char>, f64)], total_weight: f64)
+-> HashMap<Vec<char>, f64>
+{
+	// Units of three or more characters fully determine the context that
+	// follows them; shorter ones extend the context before them.
+	let mut long: HashMap<Vec<char>, f64> = HashMap::new();
+	let mut short: HashMap<Vec<char>, f64> = HashMap::new();
+	for (unit, weight) in units.iter() {
+		let p = weight / total_weight;
+		if unit.len() >= 3 {
+			*long.entry(unit[(unit.len() - 3)..].to_vec()).or_insert(0.0) += p;
+		} else {
+			*short.entry(unit.clone()).or_insert(0.0) += p;
+		}
+	}
+
+	// Before anything is known, pretend short units are preceded by nothing.
+	let mut context = long.clone();
+	for (unit, p) in short.iter() {
+		*context.entry(unit.clone()).or_insert(0.0) += p;
+	}
+	for _ in 0..CONTEXT_ITERATIONS {
+		let mut next = long.clone();
+		for (unit, p) in short.iter() {
+			for (tail, q) in context.iter() {
+				let text: Vec<char> = tail.iter().chain(unit.iter()).cloned().collect();
+				let key = text[(text.len().saturating_sub(3))..].to_vec();
+				*next.entry(key).or_insert(0.0) += p * q;
+			}
+		}
+		context = next;
+	}
+
+	context
+}
+
+// Adds the quartad ending at the last character of `text`, cut short at the
+// last character that is not on the layout, as the text counter would.
+fn add_quartad(
+	quartads:     &mut HashMap<String, f64>,
+	text:         &[char],
+	weight:           f64,
+	position_map: &LayoutPosMap)
+{
+	let mut start = text.len();
+	while start > 0 && position_map.get_key_position(text[start - 1]).is_some() {
+		start -= 1;
+	}
+	if start == text.len() {
+		return;
+	}
+
+	let quartad: String = text[start..].iter().collect();
+	*quartads.entry(quartad).or_insert(0.0) += weight;
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	use layout;
+
+	fn quartads(words: &[(&str, f64)], config: &WordListConfig)
+	-> (HashMap<String, usize>, usize)
+	{
+		let words: Vec<(String, f64)> = words.iter().map(|w| (w.0.to_string(), w.1)).collect();
+		let position_map = layout::INIT_LAYOUT.get_position_map();
+		let (QuartadList(quartads), len, _) = synthesise(&words, config, &position_map);
+		(quartads, len)
+	}
+
+	#[test]
+	fn accepts_punctuation_that_adds_up_to_one()
+	{
+		let mut config = WordListConfig::default();
+		for spec in &[".:0.1", ",:0.2", ";:0.7"] {
+			if let Err(e) = config.add_punctuation(spec) {
+				panic!("{}", e);
+			}
+		}
+		assert!(config.add_punctuation("':0.001").is_err());
+		assert_eq!(config.separators().len(), 3);
+	}
+
+	#[test]
+	fn synthesises_the_quartads_of_a_repeated_word()
+	{
+		// "ab ab ab ...": a third of the keystrokes end each quartad.
+		let (quartads, len) = quartads(&[("ab", 7.0)], &WordListConfig::default());
+		let mut expected = HashMap::new();
+		expected.insert("ab a".to_string(), 333_333_333);
+		expected.insert("b ab".to_string(), 333_333_333);
+		expected.insert(" ab ".to_string(), 333_333_333);
+		assert_eq!(quartads, expected);
+		assert_eq!(len, KEYSTROKES as usize);
+	}
+
+	#[test]
+	fn scales_frequencies_before_rounding()
+	{
+		// Frequencies rather than counts would all round to nothing unless
+		// they are scaled up first.
+		let (quartads, _) = quartads(&[("the", 0.06), ("of", 0.03), ("zax", 1e-7)], &WordListConfig::default());
+		let total: usize = quartads.values().sum();
+		assert!((total as f64 - KEYSTROKES).abs() < 100.0, "{} keystrokes", total);
+		assert!(quartads.get("zax ").is_some_and(|c| *c > 0));
+	}
+}
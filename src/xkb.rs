@@ -0,0 +1,384 @@
+//! Reading and writing layouts as XKB `symbols` files, for Linux desktops.
+//!
+//! Each `KeyMap` position goes on the ANSI key that a QWERTY typist would use
+//! for it: `AD01`-`AD11` for the top row, `AC01`-`AC11` for the home row,
+//! `AB01`-`AB10` for the bottom row, and `LALT` and `SPCE` for the thumbs. The
+//! lower and upper layers are the first two shift levels.
+
+use error::LayoutParseError;
+use layout;
+use layout::Layout;
+use export::ExportOptions;
+
+// The XKB key code at each `KeyMap` position.
+static KEY_CODES: [&str; 34] = [
+	"AD01", "AD02", "AD03", "AD04", "AD05",   "AD06", "AD07", "AD08", "AD09", "AD10", "AD11",
+	"AC01", "AC02", "AC03", "AC04", "AC05",   "AC06", "AC07", "AC08", "AC09", "AC10", "AC11",
+	"AB01", "AB02", "AB03", "AB04", "AB05",   "AB06", "AB07", "AB08", "AB09", "AB10",
+	"LALT", "SPCE"];
+
+// What the US layout has on each of those keys.
+static US_KEYS: [(char, char); 34] = [
+	('q', 'Q'), ('w', 'W'), ('e', 'E'), ('r', 'R'), ('t', 'T'),
+	('y', 'Y'), ('u', 'U'), ('i', 'I'), ('o', 'O'), ('p', 'P'), ('[', '{'),
+	('a', 'A'), ('s', 'S'), ('d', 'D'), ('f', 'F'), ('g', 'G'),
+	('h', 'H'), ('j', 'J'), ('k', 'K'), ('l', 'L'), (';', ':'), ('\'', '"'),
+	('z', 'Z'), ('x', 'X'), ('c', 'C'), ('v', 'V'), ('b', 'B'),
+	('n', 'N'), ('m', 'M'), (',', '<'), ('.', '>'), ('/', '?'),
+	('\0', '\0'), (' ', ' ')];
+
+static KEYSYMS: [(char, &str); 33] = [
+	('-', "minus"), ('=', "equal"), ('[', "bracketleft"), (']', "bracketright"),
+	('\\', "backslash"), (';', "semicolon"), ('\'', "apostrophe"), ('`', "grave"),
+	(',', "comma"), ('.', "period"), ('/', "slash"), (' ', "space"),
+	('~', "asciitilde"), ('!', "exclam"), ('@', "at"), ('#', "numbersign"),
+	('$', "dollar"), ('%', "percent"), ('^', "asciicircum"), ('&', "ampersand"),
+	('*', "asterisk"), ('(', "parenleft"), (')', "parenright"), ('_', "underscore"),
+	('+', "plus"), ('{', "braceleft"), ('}', "braceright"), ('|', "bar"),
+	(':', "colon"), ('"', "quotedbl"), ('<', "less"), ('>', "greater"),
+	('?', "question")];
+
+// Keysyms of Latin-1 characters, whose keysym values are their code points,
+// starting at U+00A0. Only read; we write other characters as `Uxxxx`.
+static LATIN1_KEYSYMS: [&str; 96] = [
+	"nobreakspace", "exclamdown", "cent", "sterling", "currency", "yen", "brokenbar", "section",
+	"diaeresis", "copyright", "ordfeminine", "guillemotleft", "notsign", "hyphen", "registered", "macron",
+	"degree", "plusminus", "twosuperior", "threesuperior", "acute", "mu", "paragraph", "periodcentered",
+	"cedilla", "onesuperior", "masculine", "guillemotright", "onequarter", "onehalf", "threequarters", "questiondown",
+	"Agrave", "Aacute", "Acircumflex", "Atilde", "Adiaeresis", "Aring", "AE", "Ccedilla",
+	"Egrave", "Eacute", "Ecircumflex", "Ediaeresis", "Igrave", "Iacute", "Icircumflex", "Idiaeresis",
+	"ETH", "Ntilde", "Ograve", "Oacute", "Ocircumflex", "Otilde", "Odiaeresis", "multiply",
+	"Oslash", "Ugrave", "Uacute", "Ucircumflex", "Udiaeresis", "Yacute", "THORN", "ssharp",
+	"agrave", "aacute", "acircumflex", "atilde", "adiaeresis", "aring", "ae", "ccedilla",
+	"egrave", "eacute", "ecircumflex", "ediaeresis", "igrave", "iacute", "icircumflex", "idiaeresis",
+	"eth", "ntilde", "ograve", "oacute", "ocircumflex", "otilde", "odiaeresis", "division",
+	"oslash", "ugrave", "uacute", "ucircumflex", "udiaeresis", "yacute", "thorn", "ydiaeresis"];
+
+pub fn render(layout: &Layout, options: &ExportOptions)
+-> String
+{
+	let mut s = String::new();
+	s.push_str(&format!("// {} layout, generated by keygen.\n", options.name));
+	s.push_str("//\n// Install as /usr/share/X11/xkb/symbols/keygen and select it with\n");
+	s.push_str("//     setxkbmap keygen\n\n");
+	s.push_str("default partial alphanumeric_keys\n");
+	s.push_str("xkb_symbols \"basic\" {\n");
+	s.push_str("    include \"us(basic)\"\n");
+	s.push_str(&format!("    name[Group1] = \"{}\";\n\n", options.name.replace('"', "'")));
+
+	let width = (0..34)
+		.map(|pos| keysym(layout.lower()[pos]).len())
+		.max().unwrap_or(0) + 1;
+	for (pos, code) in KEY_CODES.iter().enumerate() {
+		let lower = layout.lower()[pos];
+		let upper = layout.upper()[pos];
+		// Thumb keys that the layout leaves empty keep their usual function.
+		if pos >= 32 && lower == '\0' && upper == '\0' {
+			continue;
+		}
+		s.push_str(&format!("    key <{}> {{ [ {:<w$} {} ] }};\n",
+			code, format!("{},", keysym(lower)), keysym(upper), w = width));
+	}
+	s.push_str("};\n");
+
+	s
+}
+
+// Whether `s` looks like an XKB symbols file.
+pub fn is_xkb(s: &str)
+-> bool
+{
+	s.contains("xkb_symbols")
+}
+
+// Reads the `default` (or else the first) `xkb_symbols` block of `s`. Keys
+// the block doesn't define are taken from the US layout if the block includes
+// another one, since practically every layout builds on it, and are left
+// empty otherwise.
+pub fn parse(s: &str)
+-> Result<Layout, LayoutParseError>
+{
+	let s: String = s.lines()
+		.map(|line| match line.find("//") {
+			Some(i) => &line[..i],
+			None => line,
+		})
+		.collect::<Vec<_>>()
+		.join("\n");
+
+	let blocks = blocks(&s);
+	let body = match blocks.iter().find(|b| b.0).or_else(|| blocks.first()) {
+		Some(&(_, body)) => body,
+		None => return Err(LayoutParseError::new("no xkb_symbols block")),
+	};
+
+	let mut lower = ['\0'; 34];
+	let mut upper = ['\0'; 34];
+	if body.contains("include") {
+		for (pos, &(l, u)) in US_KEYS.iter().enumerate() {
+			lower[pos] = l;
+			upper[pos] = u;
+		}
+	}
+
+	let mut rest = body;
+	while let Some(i) = rest.find("key") {
+		let before = rest[..i].chars().last();
+		rest = &rest[(i + 3)..];
+		if before.map(|c| c.is_alphanumeric() || c == '_').unwrap_or(false) {
+			continue;
+		}
+		let (code, key_body) = match key_statement(rest) {
+			Some(k) => k,
+			None => continue,
+		};
+		let pos = match KEY_CODES.iter().position(|c| *c == code) {
+			Some(pos) => pos,
+			None => continue,
+		};
+		let levels = match symbols(key_body) {
+			Some(l) => l,
+			None => return Err(LayoutParseError::at_line(line_of(&s, rest),
+				format!("key <{}>: expected a list of keysyms such as [ a, A ]", code))),
+		};
+
+		let mut chars = Vec::new();
+		for sym in levels.iter().take(2) {
+			chars.push(parse_keysym(sym).ok_or_else(|| LayoutParseError::at_line(line_of(&s, rest),
+				format!("key <{}>: unknown keysym {:?}", code, sym)))?);
+		}
+		lower[pos] = chars.first().cloned().unwrap_or('\0');
+		upper[pos] = match chars.get(1) {
+			Some(&c) => c,
+			None => layout::shifted(lower[pos]),
+		};
+	}
+
+	Ok(Layout::new(lower, upper))
+}
+
+// The line of `s` that `part`, a slice of it, starts on.
+fn line_of(s: &str, part: &str)
+-> usize
+{
+	let offset = part.as_ptr() as usize - s.as_ptr() as usize;
+	s[..offset].matches('\n').count() + 1
+}
+
+// The keysym that types `kc`.
+pub fn keysym(kc: char)
+-> String
+{
+	if kc == '\0' {
+		return "NoSymbol".to_string();
+	}
+	if kc.is_ascii_alphanumeric() {
+		return kc.to_string();
+	}
+	if let Some(sym) = KEYSYMS.iter().find(|k| k.0 == kc) {
+		return sym.1.to_string();
+	}
+	if let Some(codes) = layout::key_codes(kc) {
+		return codes.xkb.to_string();
+	}
+	format!("U{:04X}", kc as u32)
+}
+
+fn parse_keysym(sym: &str)
+-> Option<char>
+{
+	// Dead keys type nothing by themselves.
+	if sym == "NoSymbol" || sym == "VoidSymbol" || sym.starts_with("dead_") {
+		return Some('\0');
+	}
+	let mut chars = sym.chars();
+	if let (Some(c), None) = (chars.next(), chars.next()) {
+		if c.is_ascii_alphanumeric() {
+			return Some(c);
+		}
+	}
+	if let Some(k) = KEYSYMS.iter().find(|k| k.1 == sym) {
+		return Some(k.0);
+	}
+	if let Some(kc) = layout::key_from_codes(|codes| codes.xkb == sym) {
+		return Some(kc);
+	}
+	if let Some(i) = LATIN1_KEYSYMS.iter().position(|k| *k == sym) {
+		return std::char::from_u32(0xa0 + i as u32);
+	}
+
+	// Uxxxx, or the equivalent keysym value 0x100xxxxx.
+	let code = if sym.starts_with('U') && sym.len() > 1 {
+		u32::from_str_radix(&sym[1..], 16).ok()
+	} else if sym.starts_with("0x100") && sym.len() == 10 {
+		u32::from_str_radix(&sym[5..], 16).ok()
+	} else {
+		None
+	};
+	code.and_then(std::char::from_u32)
+}
+
+// The `xkb_symbols` blocks of `s`: whether each is marked `default`, and its
+// body.
+fn blocks(s: &str)
+-> Vec<(bool, &str)>
+{
+	let mut blocks = Vec::new();
+	let mut start = 0;
+	while let Some(i) = s[start..].find("xkb_symbols") {
+		let header_start = start;
+		let i = start + i;
+		let open = match s[i..].find('{') {
+			Some(j) => i + j,
+			None => break,
+		};
+		let close = match matching_brace(s, open) {
+			Some(j) => j,
+			None => break,
+		};
+		// The flags before `xkb_symbols`, up to the end of the previous block.
+		let flags = &s[header_start..i];
+		let flags = &flags[flags.rfind(';').map(|j| j + 1).unwrap_or(0)..];
+		blocks.push((flags.split_whitespace().any(|f| f == "default"), &s[(open + 1)..close]));
+		start = close + 1;
+	}
+	blocks
+}
+
+fn matching_brace(s: &str, open: usize)
+-> Option<usize>
+{
+	let mut depth = 0;
+	for (i, c) in s[open..].char_indices() {
+		match c {
+			'{' => depth += 1,
+			'}' => {
+				depth -= 1;
+				if depth == 0 {
+					return Some(open + i);
+				}
+			},
+			_ => (),
+		}
+	}
+	None
+}
+
+// Splits `<CODE> { ... }` at the start of `s` into the code and the body.
+fn key_statement(s: &str)
+-> Option<(&str, &str)>
+{
+	let s = s.trim_start();
+	if !s.starts_with('<') {
+		return None;
+	}
+	let end = s.find('>')?;
+	let code = &s[1..end];
+	let rest = &s[(end + 1)..];
+	let open = rest.find('{')?;
+	if !rest[..open].trim().is_empty() {
+		return None;
+	}
+	let close = matching_brace(rest, open)?;
+	Some((code, &rest[(open + 1)..close]))
+}
+
+// The keysyms of the first group of a key body. The body is a list of
+// entries such as `type = "ALPHABETIC"`; the keysyms are either a bare list
+// `[ a, A ]` or a `symbols[Group1] = [ a, A ]` entry.
+fn symbols(body: &str)
+-> Option<Vec<String>>
+{
+	for entry in entries(body) {
+		let entry = entry.trim();
+		let list = if entry.starts_with('[') {
+			entry
+		} else if entry.starts_with("symbols") {
+			let (name, list) = entry.split_at(entry.find('=')?);
+			let group = name["symbols".len()..].trim();
+			if !(group.is_empty() || group.eq_ignore_ascii_case("[group1]")) {
+				continue;
+			}
+			list[1..].trim()
+		} else {
+			continue;
+		};
+		if !(list.starts_with('[') && list.ends_with(']')) {
+			return None;
+		}
+		return Some(list[1..(list.len() - 1)]
+			.split(',')
+			.map(|sym| sym.trim().to_string())
+			.filter(|sym| !sym.is_empty())
+			.collect());
+	}
+	None
+}
+
+// Splits a key body at the commas that are not inside brackets or quotes.
+fn entries(body: &str)
+-> Vec<&str>
+{
+	let mut entries = Vec::new();
+	let mut depth = 0;
+	let mut quoted = false;
+	let mut start = 0;
+	for (i, c) in body.char_indices() {
+		match c {
+			'"' => quoted = !quoted,
+			'[' | '(' if !quoted => depth += 1,
+			']' | ')' if !quoted => depth -= 1,
+			',' if !quoted && depth == 0 => {
+				entries.push(&body[start..i]);
+				start = i + 1;
+			},
+			_ => (),
+		}
+	}
+	entries.push(&body[start..]);
+	entries
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	#[test]
+	fn round_trip()
+	{
+		let s = render(&layout::INIT_LAYOUT, &ExportOptions::new("init"));
+		match parse(&s) {
+			Ok(layout) => {
+				assert_eq!(layout.lower(), layout::INIT_LAYOUT.lower());
+				assert_eq!(layout.upper(), layout::INIT_LAYOUT.upper());
+			},
+			Err(e) => panic!("{}", e),
+		}
+	}
+
+	#[test]
+	fn reads_keys_with_a_type()
+	{
+		let s = "xkb_symbols \"basic\" {\n    include \"us(basic)\"\n\
+		         key <AD01> { type=\"FOUR_LEVEL_ALPHABETIC\", [ j, J, adiaeresis, Adiaeresis ] };\n\
+		         key <AD02> { type[Group1] = \"TWO_LEVEL\", symbols[Group1] = [ c, C ] };\n};\n";
+		match parse(s) {
+			Ok(layout) => {
+				assert_eq!(&layout.lower()[..3], &['j', 'c', 'e']);
+				assert_eq!(&layout.upper()[..3], &['J', 'C', 'E']);
+			},
+			Err(e) => panic!("{}", e),
+		}
+	}
+
+	#[test]
+	fn reports_the_line_of_a_key_without_keysyms()
+	{
+		let s = "xkb_symbols \"basic\" {\n    key <AD01> { [ q, Q ] };\n    key <AD02> { type=\"TWO_LEVEL\" };\n};\n";
+		match parse(s) {
+			Err(e) => assert_eq!(e.line, Some(3)),
+			Ok(_) => panic!("expected an error"),
+		}
+	}
+}
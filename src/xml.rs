@@ -0,0 +1,22 @@
+//! Escaping text for the XML that the SVG, HTML and macOS layout writers emit.
+
+// Escapes text for XML, HTML and SVG. Control characters other than line
+// breaks are written as references, so that keys such as tab survive in the
+// attributes of macOS keyboard layouts.
+pub fn escape(s: &str)
+-> String
+{
+	let mut escaped = String::new();
+	for c in s.chars() {
+		match c {
+			'&' => escaped.push_str("&amp;"),
+			'<' => escaped.push_str("&lt;"),
+			'>' => escaped.push_str("&gt;"),
+			'"' => escaped.push_str("&quot;"),
+			'\'' => escaped.push_str("&apos;"),
+			c if c.is_control() && c != '\n' => escaped.push_str(&format!("&#x{:04X};", c as u32)),
+			c => escaped.push(c),
+		}
+	}
+	escaped
+}
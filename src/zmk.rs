@@ -0,0 +1,118 @@
+//! Renders a layout as a ZMK `.keymap` devicetree file.
+//!
+//! The lower layer becomes the base layer. Where the upper layer doesn't hold
+//! the symbol that shift would produce on a US keyboard, a mod-morph behavior
+//! makes shift send the upper layer's symbol instead.
+
+use layout;
+use layout::Layout;
+use export;
+use export::ExportOptions;
+
+static KEYCODES: [(char, &str); 43] = [
+	('1', "N1"), ('2', "N2"), ('3', "N3"), ('4', "N4"), ('5', "N5"),
+	('6', "N6"), ('7', "N7"), ('8', "N8"), ('9', "N9"), ('0', "N0"),
+	('-', "MINUS"), ('=', "EQUAL"), ('[', "LBKT"), (']', "RBKT"),
+	('\\', "BSLH"), (';', "SEMI"), ('\'', "SQT"), ('`', "GRAVE"),
+	(',', "COMMA"), ('.', "DOT"), ('/', "FSLH"), (' ', "SPACE"),
+	('~', "TILDE"), ('!', "EXCL"), ('@', "AT"), ('#', "HASH"),
+	('$', "DLLR"), ('%', "PRCNT"), ('^', "CARET"), ('&', "AMPS"),
+	('*', "STAR"), ('(', "LPAR"), (')', "RPAR"), ('_', "UNDER"),
+	('+', "PLUS"), ('{', "LBRC"), ('}', "RBRC"), ('|', "PIPE"),
+	(':', "COLON"), ('"', "DQT"), ('<', "LT"), ('>', "GT"),
+	('?', "QMARK")];
+
+pub fn render(layout: &Layout, options: &ExportOptions)
+-> String
+{
+	let overrides = export::shift_overrides(layout, &export::matrix_positions(options));
+	let rows: Vec<Vec<String>> = options.matrix.iter()
+		.map(|row| row.iter().map(|pos| match *pos {
+			Some(pos) if overrides.contains(&pos) => format!("&{}", behavior_name(pos)),
+			Some(pos) => binding(layout.lower()[pos]),
+			None => "&none".to_string(),
+		}).collect())
+		.collect();
+
+	let mut s = String::new();
+	s.push_str(&format!("// {} layout, generated by keygen.\n", options.name));
+	s.push_str(&export::diagram(layout, "// "));
+	s.push_str("\n#include <behaviors.dtsi>\n#include <dt-bindings/zmk/keys.h>\n\n/ {\n");
+
+	if !overrides.is_empty() {
+		// Shifted symbols that differ from a US keyboard.
+		s.push_str("    behaviors {\n");
+		for pos in overrides.iter() {
+			let name = behavior_name(*pos);
+			s.push_str(&format!("        {}: {} {{\n", name, name));
+			s.push_str("            compatible = \"zmk,behavior-mod-morph\";\n");
+			s.push_str("            #binding-cells = <0>;\n");
+			s.push_str(&format!("            bindings = <{}>, <{}>;\n",
+				binding(layout.lower()[*pos]), binding(layout.upper()[*pos])));
+			s.push_str("            mods = <(MOD_LSFT|MOD_RSFT)>;\n");
+			s.push_str("        };\n");
+		}
+		s.push_str("    };\n\n");
+	}
+
+	s.push_str("    keymap {\n        compatible = \"zmk,keymap\";\n\n");
+	s.push_str("        base_layer {\n            bindings = <\n");
+	let width = rows.iter().flat_map(|r| r.iter()).map(|b| b.len()).max().unwrap_or(0);
+	for row in rows.iter() {
+		let cells: Vec<String> = row.iter().map(|b| format!("{:<w$}", b, w = width)).collect();
+		s.push_str(&format!("                {}\n", cells.join(" ").trim_end()));
+	}
+	s.push_str("            >;\n        };\n    };\n};\n");
+
+	s
+}
+
+// The binding that types `kc`.
+pub fn binding(kc: char)
+-> String
+{
+	if kc == '\0' {
+		return "&none".to_string();
+	}
+	if kc.is_ascii_lowercase() {
+		return format!("&kp {}", kc.to_ascii_uppercase());
+	}
+	if kc.is_ascii_uppercase() {
+		return format!("&kp LS({})", kc);
+	}
+	if let Some(code) = KEYCODES.iter().find(|k| k.0 == kc) {
+		return format!("&kp {}", code.1);
+	}
+	if let Some(codes) = layout::key_codes(kc) {
+		return format!("&kp {}", codes.zmk);
+	}
+
+	// ZMK has no way to type arbitrary characters.
+	format!("&none /* U+{:04X} */", kc as u32)
+}
+
+// Name of the mod-morph behavior for the key at `pos`.
+fn behavior_name(pos: usize)
+-> String
+{
+	format!("shift_morph_{}", pos)
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+
+	// Renders match the checked-in keymaps of the reference layouts.
+	#[test]
+	fn renders_reference_layouts()
+	{
+		for &(name, layout, expected) in [
+			("qwerty", &layout::QWERTY_LAYOUT, include_str!("../testdata/export/qwerty.keymap")),
+			("dvorak", &layout::DVORAK_LAYOUT, include_str!("../testdata/export/dvorak.keymap")),
+			("init",   &layout::INIT_LAYOUT,   include_str!("../testdata/export/init.keymap")),
+		].iter() {
+			assert_eq!(render(layout, &ExportOptions::new(name)), expected, "{}", name);
+		}
+	}
+}